@@ -0,0 +1,592 @@
+use std::sync::{Arc, atomic::Ordering};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use crate::{Lanes, PERMITTED, Position, Rotation, SwitchCounter, SwitchOp, SwitchOutcome, SwitchState, switch_lane, update_lane};
+use crate::{Switcher, err::lane::{OutOfRangeError, RetireError}, err::recv::{RecvError, TryRecvError}, err::send::{SendError, SendTimeoutError, TrySendError}};
+use crate::lane_table::{Handles, LaneTable, LiveLanes};
+use crate::rotation::Rotator;
+use super::lane::{AllLanes, DynLane, OwnedEvents};
+use super::wait::wait_for_epoch;
+use super::time_slice::rotator;
+use super::{OwnedSwitchReceiverGuard, OwnedSwitchSenderGuard, SwitchReceiverGuard, SwitchReceiverGuardIterator, SwitchSenderGuard};
+
+/// Like `bounded`, but the number of lanes is decided at runtime.
+///
+/// Panics if `lanes` is 0.
+pub fn dyn_bounded<T, const S: bool, const P: bool>(lanes: usize, cap: usize) -> (DynSwitchSender<T, S>, DynSwitchReceiver<T, P>){
+    from_table(LaneTable::new(lanes, Some(cap)))
+}
+
+/// Like `unbounded`, but the number of lanes is decided at runtime.
+///
+/// Panics if `lanes` is 0.
+pub fn dyn_unbounded<T, const S: bool, const P: bool>(lanes: usize) -> (DynSwitchSender<T, S>, DynSwitchReceiver<T, P>){
+    from_table(LaneTable::new(lanes, None))
+}
+
+fn from_table<T, const S: bool, const P: bool>(table: LaneTable<DynLane<T>>) -> (DynSwitchSender<T, S>, DynSwitchReceiver<T, P>){
+    let switch = Arc::new(SwitchCounter::new());
+    let table = Arc::new(table);
+    let handles = Arc::new(Handles::new());
+
+    (
+        DynSwitchSender{
+            count: switch.clone(),
+            table: table.clone(),
+            handles: handles.clone(),
+        },
+        DynSwitchReceiver{
+            count: switch,
+            table,
+            handles,
+            rotation: None,
+        }
+    )
+}
+
+/// Sends into the active lane, following the switch if that lane is retired while waiting for room.
+async fn send<T>(table: &LaneTable<DynLane<T>>, count: &SwitchCounter, mut msg: T) -> Result<(), SendError<T>>{
+    loop{
+        let lane = table.active(count);
+        match lane.sender.send(msg).await{
+            Ok(()) => {
+                lane.events.sent();
+                return Ok(());
+            },
+            Err(async_std::channel::SendError(returned)) if !table.is_closed() => msg = returned,
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Tries to send into the active lane, following the switch if that lane has been retired.
+fn try_send<T>(table: &LaneTable<DynLane<T>>, count: &SwitchCounter, mut msg: T) -> Result<(), TrySendError<T>>{
+    loop{
+        let lane = table.active(count);
+        match lane.sender.try_send(msg){
+            Ok(()) => {
+                lane.events.sent();
+                return Ok(());
+            },
+            Err(async_std::channel::TrySendError::Closed(returned)) if !table.is_closed() => msg = returned,
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Retires the lane at position `lane`, moving whatever is left in it to the back of the lane at position `to`.
+async fn retire<T>(table: &LaneTable<DynLane<T>>, count: &SwitchCounter, lane: usize, to: usize) -> Result<(), RetireError>{
+    let (retired, target) = table.retire(count, lane, to)?;
+    while let Ok(msg) = retired.receiver.try_recv(){
+        retired.events.received();
+        match target.sender.send(msg).await{
+            Ok(()) => target.events.sent(),
+            // If `to` was retired in the meantime as well, follow the switch instead.
+            Err(async_std::channel::SendError(msg)) => if send(table, count, msg).await.is_err(){
+                break;
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Like `retire`, but never waits for room, returning whatever didn't fit in the lane at `to`, in order.
+fn retire_now<T>(table: &LaneTable<DynLane<T>>, count: &SwitchCounter, lane: usize, to: usize) -> Result<Vec<T>, RetireError>{
+    let (retired, target) = table.retire(count, lane, to)?;
+    let mut left = Vec::new();
+    while let Ok(msg) = retired.receiver.try_recv(){
+        retired.events.received();
+        // Once a message doesn't fit, the ones after it stay behind as well, so they keep their order.
+        if !left.is_empty(){
+            left.push(msg);
+            continue;
+        }
+        let sent = match target.sender.try_send(msg){
+            Ok(()) => {
+                target.events.sent();
+                Ok(())
+            },
+            // If `to` was retired in the meantime as well, follow the switch instead.
+            Err(async_std::channel::TrySendError::Closed(msg)) => try_send(table, count, msg),
+            Err(err) => Err(err.into()),
+        };
+        if let Err(TrySendError::Full(msg) | TrySendError::Closed(msg)) = sent{
+            left.push(msg);
+        }
+    }
+    Ok(left)
+}
+
+/// A `SwitchSender` whose number of lanes is only known at runtime, and can change while it's in use.
+///
+/// The guards are the same as those of `SwitchSender`. Sending through the handle itself
+/// follows the switch if the active lane is retired, while a guard for a retired lane finds it closed.
+pub struct DynSwitchSender<T, const P: bool>{
+    count: Arc<SwitchCounter>,
+    table: Arc<LaneTable<DynLane<T>>>,
+    handles: Arc<Handles>,
+}
+
+impl<T, const P: bool> DynSwitchSender<T, P>{
+    fn active(&self) -> &DynLane<T>{
+        self.table.active(&self.count)
+    }
+
+    pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>>{
+        try_send(&self.table, &self.count, msg)
+    }
+
+    pub async fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
+        send(&self.table, &self.count, msg).await
+    }
+
+    /// Send into the activate channel, handing the message back if it's still full after `timeout`.
+    pub async fn send_timeout(&'_ self, msg: T, timeout: Duration) -> Result<(), SendTimeoutError<T>>{
+        match Instant::now().checked_add(timeout){
+            Some(deadline) => self.send_deadline(msg, deadline).await,
+            // Too far in the future to ever time out.
+            None => Ok(self.send(msg).await?),
+        }
+    }
+
+    /// Send into the activate channel, handing the message back if it's still full at `deadline`.
+    pub async fn send_deadline(&'_ self, mut msg: T, deadline: Instant) -> Result<(), SendTimeoutError<T>>{
+        loop{
+            let lane = self.active();
+            match lane.events.send_deadline(&lane.sender, msg, deadline).await{
+                Err(SendTimeoutError::Closed(returned)) if !self.table.is_closed() => msg = returned,
+                sent => return sent,
+            }
+        }
+    }
+
+    pub fn close(&self) -> bool{
+        self.table.close()
+    }
+
+    pub fn is_closed(&self) -> bool{
+        self.table.is_closed()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.active().sender.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.active().sender.is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.active().sender.len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.table.capacity()
+    }
+
+    /// Returns the number of lanes in the channel.
+    pub fn lanes(&self) -> usize{
+        self.table.lanes().len()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.handles.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.handles.receiver_count()
+    }
+
+    /// Returns the epoch the channel is in, which moves on every time the channel switches to a different lane.
+    pub fn epoch(&self) -> usize{
+        self.count.epoch()
+    }
+
+    fn get_guard(&self) -> SwitchSenderGuard<'_, T>{
+        let lanes = self.table.lanes();
+        self.guard(&lanes, self.count.state(lanes.len()))
+    }
+
+    /// Wait until another handle switches the channel to a different lane, returning a guard for the new lane.
+    pub async fn switched(&self) -> SwitchSenderGuard<'_, T>{
+        wait_for_epoch(&self.count, self.count.epoch()).await;
+        self.get_guard()
+    }
+
+    /// Get a guard for `lane` without switching the channel.
+    pub fn guard_for(&self, lane: usize) -> Result<SwitchSenderGuard<'_, T>, OutOfRangeError>{
+        let lanes = self.table.lanes();
+        if lane < lanes.len(){
+            Ok(self.guard(&lanes, SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: lanes.len() })
+        }
+    }
+
+    /// The same as `guard_for`, so a specific lane can be used as `sender.send_to(lane)?.send(msg)`.
+    pub fn send_to(&self, lane: usize) -> Result<SwitchSenderGuard<'_, T>, OutOfRangeError>{
+        self.guard_for(lane)
+    }
+
+    fn guard<'a>(&'a self, lanes: &LiveLanes<'a, DynLane<T>>, state: SwitchState) -> SwitchSenderGuard<'a, T>{
+        let lane = lanes.get(state.lane);
+        SwitchSenderGuard::new(&lane.sender, &lane.events, AllLanes::Table(&self.table), &self.handles, Position{ count: &self.count, state, lanes: Lanes::Table(self.table.live_slots(), lanes.slot(state.lane)) })
+    }
+
+    /// Get a guard for the active lane that keeps its own handle on the lane, so it can be moved into a spawned task.
+    pub fn get_owned_guard(&self) -> OwnedSwitchSenderGuard<T>{
+        let lanes = self.table.lanes();
+        self.owned_guard(&lanes, self.count.state(lanes.len()))
+    }
+
+    /// Like `guard_for`, but the guard keeps its own handle on the lane, so it can be moved into a spawned task.
+    pub fn owned_guard_for(&self, lane: usize) -> Result<OwnedSwitchSenderGuard<T>, OutOfRangeError>{
+        let lanes = self.table.lanes();
+        if lane < lanes.len(){
+            Ok(self.owned_guard(&lanes, SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: lanes.len() })
+        }
+    }
+
+    fn owned_guard(&self, lanes: &LiveLanes<'_, DynLane<T>>, state: SwitchState) -> OwnedSwitchSenderGuard<T>{
+        self.handles.add_sender();
+        OwnedSwitchSenderGuard{
+            sender: lanes.get(state.lane).sender.clone(),
+            events: OwnedEvents::Table(self.table.clone(), self.handles.clone(), lanes.slot(state.lane)),
+            state,
+            count: self.count.clone(),
+        }
+    }
+}
+
+impl<T> DynSwitchSender<T, PERMITTED>{
+    /// Add a lane after the others, returning its position. It has the same capacity as the other lanes.
+    pub fn add_lane(&self) -> usize{
+        self.table.add()
+    }
+
+    /// Retire the lane at position `lane` without closing the channel.
+    ///
+    /// Whatever is left in it moves to the back of the lane at position `to`, waiting while that lane is full,
+    /// and if it was the active lane, the channel switches to `to`. The lanes after it move down a position,
+    /// which counts as a switch if the active lane moves. Guards for the retired lane find it closed.
+    ///
+    /// A retired lane is only freed along with the channel, since guards can still be borrowing it,
+    /// so a channel that keeps adding and retiring lanes keeps growing until it's dropped.
+    pub async fn retire_lane(&self, lane: usize, to: usize) -> Result<(), RetireError>{
+        retire(&self.table, &self.count, lane, to).await
+    }
+
+    /// Like `switch_outcome`, but with owned guards.
+    pub fn switch_outcome_owned(&self, op: SwitchOp) -> SwitchOutcome<OwnedSwitchSenderGuard<T>>{
+        let lanes = self.table.lanes();
+        SwitchOutcome::from_states(switch_lane(&self.count, lanes.len(), op), |state| self.owned_guard(&lanes, state))
+    }
+
+    /// Like `switch_update`, but with owned guards.
+    pub fn switch_update_owned<F: FnMut(usize) -> Option<usize>>(&self, f: F) -> Result<OwnedSwitchSenderGuard<T>, OwnedSwitchSenderGuard<T>>{
+        let lanes = self.table.lanes();
+        let guard = |state: SwitchState| self.owned_guard(&lanes, state);
+        update_lane(&self.count, lanes.len(), f).map(guard).map_err(guard)
+    }
+}
+
+impl<'a, T: 'static> Switcher<'a, T> for DynSwitchSender<T, PERMITTED>{
+    type Output = SwitchSenderGuard<'a, T>;
+
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<SwitchSenderGuard<'a, T>>{
+        let lanes = self.table.lanes();
+        SwitchOutcome::from_states(switch_lane(&self.count, lanes.len(), op), |state| self.guard(&lanes, state))
+    }
+
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<SwitchSenderGuard<'a, T>, SwitchSenderGuard<'a, T>>{
+        let lanes = self.table.lanes();
+        let guard = |state: SwitchState| self.guard(&lanes, state);
+        update_lane(&self.count, lanes.len(), f).map(guard).map_err(guard)
+    }
+}
+
+impl<T, const P: bool> Clone for DynSwitchSender<T, P>{
+    fn clone(&self) -> Self{
+        self.handles.add_sender();
+        Self{
+            count: self.count.clone(),
+            table: self.table.clone(),
+            handles: self.handles.clone(),
+        }
+    }
+}
+
+impl<T, const P: bool> Drop for DynSwitchSender<T, P>{
+    fn drop(&mut self){
+        // The lanes hold on to both ends of their channels, so they're closed here once the last sender is gone.
+        if self.handles.remove_sender(){
+            self.close();
+        }
+    }
+}
+
+/// A `SwitchReceiver` whose number of lanes is only known at runtime, and can change while it's in use.
+///
+/// The guards are the same as those of `SwitchReceiver`. Receiving through the handle itself
+/// follows the switch if the active lane is retired, while a guard for a retired lane finds it closed.
+pub struct DynSwitchReceiver<T, const P: bool>{
+    count: Arc<SwitchCounter>,
+    table: Arc<LaneTable<DynLane<T>>>,
+    handles: Arc<Handles>,
+    rotation: Option<Rotator>,
+}
+
+impl<T, const P: bool> DynSwitchReceiver<T, P>{
+    fn active(&self) -> &DynLane<T>{
+        self.table.active(&self.count)
+    }
+
+    /// Try to receive from the activate channel.
+    ///
+    /// With a rotation set, this moves on past empty lanes, so it only fails if every lane is empty.
+    pub fn try_recv(&self) -> Result<T, TryRecvError>{
+        if let Some(rotator) = &self.rotation{
+            let lanes = self.table.lanes();
+            return rotator.try_recv(&self.count, lanes.len(), |lane| {
+                let lane = lanes.get(lane);
+                let msg = lane.receiver.try_recv()?;
+                lane.events.received();
+                Ok(msg)
+            });
+        }
+        loop{
+            match self.get_guard().try_recv(){
+                // The lane was retired, follow the switch to the lane that replaced it.
+                Err(TryRecvError::Closed) if !self.table.is_closed() => {},
+                received => return received,
+            }
+        }
+    }
+
+    /// receive from the activate channel.
+    ///
+    /// With a rotation set, this waits for a message on any lane.
+    pub async fn recv(&'_ self) -> Result<T, RecvError>{
+        if self.rotation.is_none(){
+            loop{
+                match self.get_guard().recv().await{
+                    Err(RecvError) if !self.table.is_closed() => {},
+                    received => return received,
+                }
+            }
+        }
+        loop{
+            // Start listening before trying, so a message sent in between isn't missed.
+            // A lane added in the meantime isn't listened to, so listen for that as well.
+            let mut listeners: Vec<_> = self.table.lanes().iter().map(|lane| lane.events.listen_ready()).collect();
+            listeners.push(self.table.listen_changed());
+            match self.try_recv(){
+                Ok(msg) => return Ok(msg),
+                Err(TryRecvError::Closed) => return Err(RecvError),
+                Err(TryRecvError::Empty) => {},
+            }
+            std::future::poll_fn(|cx| {
+                if listeners.iter_mut().any(|listener| Pin::new(listener).poll(cx).is_ready()){
+                    Poll::Ready(())
+                }else{
+                    Poll::Pending
+                }
+            }).await;
+        }
+    }
+
+    /// Close all the channels.
+    pub fn close(&self) -> bool{
+        self.table.close()
+    }
+
+    /// Checks if all the channels have been closed.
+    pub fn is_closed(&self) -> bool{
+        self.table.is_closed()
+    }
+
+    /// Check if the activate channel is empty.
+    pub fn is_empty(&self) -> bool{
+        self.active().receiver.is_empty()
+    }
+
+    /// Check if the activate channel is full, unbounded channels are never full.
+    pub fn is_full(&self) -> bool{
+        self.active().receiver.is_full()
+    }
+
+    /// Returns the number of messages in the activate channel.
+    pub fn len(&self) -> usize{
+        self.active().receiver.len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.table.capacity()
+    }
+
+    /// Returns the number of lanes in the channel.
+    pub fn lanes(&self) -> usize{
+        self.table.lanes().len()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.handles.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.handles.receiver_count()
+    }
+
+    /// Returns the epoch the channel is in, which moves on every time the channel switches to a different lane.
+    pub fn epoch(&self) -> usize{
+        self.count.epoch()
+    }
+
+    /// Iterate over the messages already in the active lane, without waiting for more.
+    pub fn try_iter(&self) -> SwitchReceiverGuardIterator<'_, T>{
+        let lane = self.active();
+        SwitchReceiverGuardIterator{
+            receiver: &lane.receiver,
+            events: &lane.events,
+        }
+    }
+
+    pub fn get_guard(&self) -> SwitchReceiverGuard<'_, T>{
+        let lanes = self.table.lanes();
+        self.guard(&lanes, self.count.state(lanes.len()))
+    }
+
+    /// Wait until another handle switches the channel to a different lane, returning a guard for the new lane.
+    pub async fn switched(&self) -> SwitchReceiverGuard<'_, T>{
+        self.get_guard().switched().await;
+        self.get_guard()
+    }
+
+    /// Get a guard for `lane` without switching the channel.
+    pub fn guard_for(&self, lane: usize) -> Result<SwitchReceiverGuard<'_, T>, OutOfRangeError>{
+        let lanes = self.table.lanes();
+        if lane < lanes.len(){
+            Ok(self.guard(&lanes, SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: lanes.len() })
+        }
+    }
+
+    /// The same as `guard_for`, so a specific lane can be used as `receiver.recv_from(lane)?.recv()`.
+    pub fn recv_from(&self, lane: usize) -> Result<SwitchReceiverGuard<'_, T>, OutOfRangeError>{
+        self.guard_for(lane)
+    }
+
+    fn guard<'a>(&'a self, lanes: &LiveLanes<'a, DynLane<T>>, state: SwitchState) -> SwitchReceiverGuard<'a, T>{
+        let lane = lanes.get(state.lane);
+        SwitchReceiverGuard::new(&lane.receiver, &lane.events, &self.handles, Position{ count: &self.count, state, lanes: Lanes::Table(self.table.live_slots(), lanes.slot(state.lane)) })
+    }
+
+    /// Like `get_guard`, but the guard keeps its own handle on the lane, so it can be moved into a spawned task.
+    pub fn get_owned_guard(&self) -> OwnedSwitchReceiverGuard<T>{
+        let lanes = self.table.lanes();
+        self.owned_guard(&lanes, self.count.state(lanes.len()))
+    }
+
+    /// Like `guard_for`, but the guard keeps its own handle on the lane, so it can be moved into a spawned task.
+    pub fn owned_guard_for(&self, lane: usize) -> Result<OwnedSwitchReceiverGuard<T>, OutOfRangeError>{
+        let lanes = self.table.lanes();
+        if lane < lanes.len(){
+            Ok(self.owned_guard(&lanes, SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: lanes.len() })
+        }
+    }
+
+    fn owned_guard(&self, lanes: &LiveLanes<'_, DynLane<T>>, state: SwitchState) -> OwnedSwitchReceiverGuard<T>{
+        self.handles.add_receiver();
+        OwnedSwitchReceiverGuard{
+            receiver: lanes.get(state.lane).receiver.clone(),
+            events: OwnedEvents::Table(self.table.clone(), self.handles.clone(), lanes.slot(state.lane)),
+            state,
+            count: self.count.clone(),
+        }
+    }
+}
+
+impl<T> DynSwitchReceiver<T, PERMITTED>{
+    /// Let the receiver switch lanes by itself according to `rotation`, like `SwitchReceiver::with_rotation`.
+    ///
+    /// Panics if `rotation` is `Weighted` with a different number of weights than lanes, or a weight of 0.
+    /// Lanes added later on get a weight of 1, and weights stay with their position when a lane is retired.
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self{
+        let live = self.table.live_count();
+        self.rotation = Some(rotator(rotation, &self.count, move || live.load(Ordering::SeqCst)));
+        self
+    }
+
+    /// Add a lane after the others, returning its position. It has the same capacity as the other lanes.
+    pub fn add_lane(&self) -> usize{
+        self.table.add()
+    }
+
+    /// Retire the lane at position `lane` without closing the channel, like `DynSwitchSender::retire_lane`.
+    ///
+    /// Only what fits in the lane at position `to` is moved over, since waiting for room could wait on this very receiver.
+    /// The rest is returned in order, and is no longer in the channel.
+    pub fn retire_lane(&self, lane: usize, to: usize) -> Result<Vec<T>, RetireError>{
+        retire_now(&self.table, &self.count, lane, to)
+    }
+
+    /// Like `switch_outcome`, but with owned guards.
+    pub fn switch_outcome_owned(&self, op: SwitchOp) -> SwitchOutcome<OwnedSwitchReceiverGuard<T>>{
+        let lanes = self.table.lanes();
+        SwitchOutcome::from_states(switch_lane(&self.count, lanes.len(), op), |state| self.owned_guard(&lanes, state))
+    }
+
+    /// Like `switch_update`, but with owned guards.
+    pub fn switch_update_owned<F: FnMut(usize) -> Option<usize>>(&self, f: F) -> Result<OwnedSwitchReceiverGuard<T>, OwnedSwitchReceiverGuard<T>>{
+        let lanes = self.table.lanes();
+        let guard = |state: SwitchState| self.owned_guard(&lanes, state);
+        update_lane(&self.count, lanes.len(), f).map(guard).map_err(guard)
+    }
+}
+
+impl<'a, T: 'static> Switcher<'a, T> for DynSwitchReceiver<T, PERMITTED>{
+    type Output = SwitchReceiverGuard<'a, T>;
+
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<SwitchReceiverGuard<'a, T>>{
+        let lanes = self.table.lanes();
+        SwitchOutcome::from_states(switch_lane(&self.count, lanes.len(), op), |state| self.guard(&lanes, state))
+    }
+
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<SwitchReceiverGuard<'a, T>, SwitchReceiverGuard<'a, T>>{
+        let lanes = self.table.lanes();
+        let guard = |state: SwitchState| self.guard(&lanes, state);
+        update_lane(&self.count, lanes.len(), f).map(guard).map_err(guard)
+    }
+}
+
+impl<T, const P: bool> Clone for DynSwitchReceiver<T, P>{
+    fn clone(&self) -> Self{
+        self.handles.add_receiver();
+        Self{
+            count: self.count.clone(),
+            table: self.table.clone(),
+            handles: self.handles.clone(),
+            rotation: self.rotation.clone(),
+        }
+    }
+}
+
+impl<T, const P: bool> Drop for DynSwitchReceiver<T, P>{
+    fn drop(&mut self){
+        // Close the lanes here like `SwitchReceiver` does, so senders waiting for room get woken up.
+        if self.handles.remove_receiver(){
+            self.close();
+        }
+    }
+}
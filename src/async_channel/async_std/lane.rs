@@ -0,0 +1,219 @@
+use std::sync::Arc;
+use std::time::Instant;
+use async_std::channel::{Receiver, Sender};
+use event_listener::{Event, EventListener};
+use crate::err::send::SendTimeoutError;
+use crate::Lanes;
+use crate::lane_table::{Handles, LaneTable, TableLane};
+
+/// Events for a single lane of an async_std switch channel.
+///
+/// async_std doesn't expose the events of its own channels,
+/// so the switch channel handles notify these whenever they put messages into a lane, take them out, or close it.
+pub(crate) struct LaneEvents{
+    space: Event,
+    // Every listener is woken up, since selects don't take the message.
+    ready: Event,
+}
+
+impl LaneEvents{
+    pub(crate) fn new() -> Self{
+        Self{
+            space: Event::new(),
+            ready: Event::new(),
+        }
+    }
+
+    pub(crate) fn sent(&self){
+        self.ready.notify(usize::MAX);
+    }
+
+    pub(crate) fn received(&self){
+        self.space.notify_additional(1);
+    }
+
+    pub(crate) fn closed(&self){
+        self.space.notify(usize::MAX);
+        self.ready.notify(usize::MAX);
+    }
+
+    pub(crate) fn listen_ready(&self) -> EventListener{
+        self.ready.listen()
+    }
+
+    pub(crate) fn listen_space(&self) -> EventListener{
+        self.space.listen()
+    }
+
+    /// Send `msg` into `sender`, handing it back if the lane is still full at `deadline`.
+    pub(crate) async fn send_deadline<T>(&self, sender: &Sender<T>, mut msg: T, deadline: Instant) -> Result<(), SendTimeoutError<T>>{
+        loop{
+            // Start listening before trying, so room made in between isn't missed.
+            let listener = self.space.listen();
+            match sender.try_send(msg){
+                Ok(()) => {
+                    self.sent();
+                    return Ok(());
+                },
+                Err(async_std::channel::TrySendError::Closed(returned)) => return Err(SendTimeoutError::Closed(returned)),
+                Err(async_std::channel::TrySendError::Full(returned)) => msg = returned,
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if async_std::future::timeout(timeout, listener).await.is_err(){
+                return Err(SendTimeoutError::Timeout(msg));
+            }
+        }
+    }
+}
+
+/// A lane of an async_std switch channel that keeps its channel together with its events,
+/// so it can be kept in a `LaneTable`.
+///
+/// The lane holds on to both ends of its channel, so the table's handles are counted with `Handles` instead.
+pub(crate) struct DynLane<T>{
+    pub(crate) sender: Sender<T>,
+    pub(crate) receiver: Receiver<T>,
+    pub(crate) events: LaneEvents,
+}
+
+/// A bounded channel with room for `capacity` messages, or an unbounded one.
+pub(crate) fn channel<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>){
+    match capacity{
+        Some(cap) => async_std::channel::bounded(cap),
+        None => async_std::channel::unbounded(),
+    }
+}
+
+impl<T> TableLane for DynLane<T>{
+    fn new(capacity: Option<usize>) -> Self{
+        let (sender, receiver) = channel(capacity);
+        Self{
+            sender,
+            receiver,
+            events: LaneEvents::new(),
+        }
+    }
+
+    fn close(&self) -> bool{
+        let closed = self.sender.close();
+        self.events.closed();
+        closed
+    }
+}
+
+/// Every lane of the channel a sender guard was taken from, so closing the guard as a sink can close all of them.
+pub(crate) enum AllLanes<'a, T>{
+    Fixed(&'a [Sender<T>], &'a [LaneEvents]),
+    Table(&'a LaneTable<DynLane<T>>),
+}
+
+impl<'a, T> AllLanes<'a, T>{
+    /// Closes every lane, returning `true` if this call closed any of them.
+    pub(crate) fn close(&self) -> bool{
+        match self{
+            Self::Fixed(senders, events) => close_lanes(senders, events),
+            Self::Table(table) => table.close(),
+        }
+    }
+}
+
+impl<'a, T> Clone for AllLanes<'a, T>{
+    fn clone(&self) -> Self{
+        *self
+    }
+}
+
+impl<'a, T> Copy for AllLanes<'a, T>{}
+
+/// One end of an async_std channel. Closing either end closes the whole lane.
+pub(crate) trait LaneEnd{
+    fn close(&self) -> bool;
+}
+
+impl<T> LaneEnd for Sender<T>{
+    fn close(&self) -> bool{
+        Sender::close(self)
+    }
+}
+
+impl<T> LaneEnd for Receiver<T>{
+    fn close(&self) -> bool{
+        Receiver::close(self)
+    }
+}
+
+/// Closes every lane through `ends`, returning `true` if this call closed any of them.
+pub(crate) fn close_lanes<E: LaneEnd>(ends: &[E], events: &[LaneEvents]) -> bool{
+    // Any number of threads could call close, but only one
+    // will be the first to close each lane. Every lane has
+    // to be closed, so this can't stop at the first one.
+    let mut closed = false;
+    for (end, events) in ends.iter().zip(events.iter()){
+        closed |= end.close();
+        events.closed();
+    }
+    closed
+}
+
+/// The lanes an owned guard keeps alive, and closes if it turns out to be the channel's last handle.
+///
+/// `E` is the end of the channel the guard sends or receives with.
+pub(crate) enum OwnedEvents<T, E>{
+    // Every lane's end, not just the guard's own, since the guard can close the whole channel.
+    Fixed(Arc<[E]>, Arc<[LaneEvents]>, Arc<Handles>),
+    // A lane of a table is kept by its slot, since its position can change.
+    Table(Arc<LaneTable<DynLane<T>>>, Arc<Handles>, usize),
+}
+
+impl<T, E> OwnedEvents<T, E>{
+    /// The events of the lane at position `lane`, or of the guard's own lane for a table.
+    pub(crate) fn get(&self, lane: usize) -> &LaneEvents{
+        match self{
+            Self::Fixed(_, events, _) => &events[lane],
+            Self::Table(table, _, slot) => &table.slot(*slot).events,
+        }
+    }
+
+    /// Where the guard's lane is among the channel's lanes.
+    pub(crate) fn lanes(&self) -> Lanes<'_>{
+        match self{
+            Self::Fixed(_, events, _) => Lanes::Fixed(events.len()),
+            Self::Table(table, _, slot) => Lanes::Table(table.live_slots(), *slot),
+        }
+    }
+
+    pub(crate) fn handles(&self) -> &Handles{
+        match self{
+            Self::Fixed(_, _, handles) => handles,
+            Self::Table(_, handles, _) => handles,
+        }
+    }
+}
+
+impl<T, E: LaneEnd> OwnedEvents<T, E>{
+    pub(crate) fn close(&self) -> bool{
+        match self{
+            Self::Fixed(ends, events, _) => close_lanes(ends, events),
+            Self::Table(table, _, _) => table.close(),
+        }
+    }
+}
+
+impl<T> OwnedEvents<T, Sender<T>>{
+    /// The lanes a guard borrowed from the owned guard can close.
+    pub(crate) fn all_lanes(&self) -> AllLanes<'_, T>{
+        match self{
+            Self::Fixed(senders, events, _) => AllLanes::Fixed(senders, events),
+            Self::Table(table, _, _) => AllLanes::Table(table),
+        }
+    }
+}
+
+impl<T, E> Clone for OwnedEvents<T, E>{
+    fn clone(&self) -> Self{
+        match self{
+            Self::Fixed(ends, events, handles) => Self::Fixed(ends.clone(), events.clone(), handles.clone()),
+            Self::Table(table, handles, slot) => Self::Table(table.clone(), handles.clone(), *slot),
+        }
+    }
+}
@@ -1,50 +1,99 @@
+mod lane;
 mod switch_receiver;
 mod switch_sender;
 mod diswitchchannel;
+mod dynswitchchannel;
+mod select;
+mod wait;
+mod time_slice;
 
 use std::convert::TryInto;
-use core::sync::atomic::AtomicUsize;
+use crate::SwitchCounter;
 use std::sync::Arc;
 use core::iter::repeat_with;
-pub use switch_receiver::{SwitchReceiver, SwitchReceiverGuard, SwitchReceiverGuardIterator};
-pub use switch_sender::{SwitchSender, SwitchSenderGuard};
+use crate::SwitchGroup;
+use lane::LaneEvents;
+use crate::lane_table::Handles;
+pub use switch_receiver::{SwitchReceiver, SwitchReceiverGuard, OwnedSwitchReceiverGuard, SwitchReceiverGuardIterator, SwitchReceiverGuardDrain};
+pub use switch_sender::{SwitchSender, SwitchSenderGuard, OwnedSwitchSenderGuard};
+pub use select::Select;
 pub use diswitchchannel::{dibounded, diunbounded, DiSwitchSender, DiSwitchReceiver};
+pub use dynswitchchannel::{dyn_bounded, dyn_unbounded, DynSwitchSender, DynSwitchReceiver};
 
 
 pub fn bounded<T, const N: usize, const S: bool, const P: bool>(cap: usize) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    let switch = Arc::new(SwitchCounter::new());
+    bounded_with_switches(cap, switch.clone(), switch)
+}
+
+/// Like `bounded`, but the senders and receivers each get their own switch counter,
+/// so switching one half doesn't redirect the other.
+pub fn bounded_decoupled<T, const N: usize, const S: bool, const P: bool>(cap: usize) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    bounded_with_switches(cap, Arc::new(SwitchCounter::new()), Arc::new(SwitchCounter::new()))
+}
+
+pub fn unbounded<T, const N: usize, const S: bool, const P: bool>() -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    let switch = Arc::new(SwitchCounter::new());
+    unbounded_with_switches(switch.clone(), switch)
+}
+
+/// Like `unbounded`, but the senders and receivers each get their own switch counter,
+/// so switching one half doesn't redirect the other.
+pub fn unbounded_decoupled<T, const N: usize, const S: bool, const P: bool>() -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    unbounded_with_switches(Arc::new(SwitchCounter::new()), Arc::new(SwitchCounter::new()))
+}
+
+/// Like `bounded`, but every lane gets its own capacity, `None` for an unbounded lane.
+///
+/// `bounded_with([64, 4096])` makes a small first lane and a large second one,
+/// while `bounded_with([Some(64), None])` leaves the second lane unbounded.
+pub fn bounded_with<T, const N: usize, const S: bool, const P: bool, C: Into<Option<usize>>>(caps: [C; N]) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    let switch = Arc::new(SwitchCounter::new());
+    let (senders, receivers) = IntoIterator::into_iter(caps).map(|cap| lane::channel(cap.into())).unzip();
+    with_lanes(senders, receivers, switch.clone(), switch)
+}
+
+/// Like `bounded`, but the channel switches together with every other channel in `group`.
+pub fn bounded_in<T, const N: usize, const S: bool, const P: bool>(group: &SwitchGroup<N>, cap: usize) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    bounded_with_switches(cap, group.count.clone(), group.count.clone())
+}
+
+/// Like `unbounded`, but the channel switches together with every other channel in `group`.
+pub fn unbounded_in<T, const N: usize, const S: bool, const P: bool>(group: &SwitchGroup<N>) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    unbounded_with_switches(group.count.clone(), group.count.clone())
+}
+
+pub(crate) fn bounded_with_switches<T, const N: usize, const S: bool, const P: bool>(cap: usize, sender_switch: Arc<SwitchCounter>, receiver_switch: Arc<SwitchCounter>) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
     use async_std::channel::{bounded, Sender, Receiver};
     
     let (senders, receivers): (Vec<Sender<T>>, Vec<Receiver<T>>) = repeat_with(|| bounded(cap)).take(N).unzip();
 
-    let switch = Arc::new(AtomicUsize::new(0));
+    with_lanes(senders, receivers, sender_switch, receiver_switch)
+}
+
+fn with_lanes<T, const N: usize, const S: bool, const P: bool>(senders: Vec<async_std::channel::Sender<T>>, receivers: Vec<async_std::channel::Receiver<T>>, sender_switch: Arc<SwitchCounter>, receiver_switch: Arc<SwitchCounter>) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    let events = Arc::new(std::array::from_fn(|_| LaneEvents::new()));
+    let handles = Arc::new(Handles::new());
+
     (
-        SwitchSender{
-            count: switch.clone(),
-            senders: senders.try_into().unwrap()
-        },
+        SwitchSender::new(sender_switch, senders.try_into().unwrap(), events.clone(), handles.clone()),
         SwitchReceiver{
-            count: switch.clone(),
-            receivers: receivers.try_into().unwrap()
+            count: receiver_switch,
+            receivers: receivers.try_into().unwrap(),
+            events,
+            handles,
+            rotation: None,
+            switched: None,
         }
     )
 }
 
-pub fn unbounded<T, const N: usize, const S: bool, const P: bool>() -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+pub(crate) fn unbounded_with_switches<T, const N: usize, const S: bool, const P: bool>(sender_switch: Arc<SwitchCounter>, receiver_switch: Arc<SwitchCounter>) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
     use async_std::channel::{unbounded, Sender, Receiver};
     
     let (senders, receivers): (Vec<Sender<T>>, Vec<Receiver<T>>) = repeat_with(|| unbounded()).take(N).unzip();
 
-    let switch = Arc::new(AtomicUsize::new(0));
-    (
-        SwitchSender{
-            count: switch.clone(),
-            senders: senders.try_into().unwrap()
-        },
-        SwitchReceiver{
-            count: switch.clone(),
-            receivers: receivers.try_into().unwrap()
-        }
-    )
+    with_lanes(senders, receivers, sender_switch, receiver_switch)
 }
 
 
@@ -53,7 +102,7 @@ mod tests{
     use crate::*;
     use super::*;
     use futures::{future, FutureExt};
-    use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    use std::sync::{Arc, atomic::Ordering};
     use async_std::task;
 
     #[test]
@@ -107,6 +156,762 @@ mod tests{
         Ok(())
     }
 
+    #[async_std::test]
+    async fn switch_add_sub_wraps() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<usize, 3, true, true>();
+        for i in 0..3000{
+            sender.switch_add(1).send(i).await?;
+        }
+        // Subtracting from lane 0 wraps around to the last lane.
+        assert_eq!(Ok(0), receiver.switch_sub(1).try_recv());
+        assert_eq!(Ok(2), receiver.switch_sub(1).try_recv());
+        assert_eq!(Ok(1), receiver.switch_sub(1).try_recv());
+        assert_eq!(Ok(3), receiver.get_guard().try_recv());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn switch_outcome_returns_both_lanes() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 2, true, true>();
+        let outcome = sender.switch_outcome(SwitchOp::Xor(1));
+        assert_eq!((0, 1), (outcome.previous_lane, outcome.current_lane));
+        outcome.previous.send(10).await?;
+        outcome.current.send(20).await?;
+
+        let outcome = receiver.switch_outcome(SwitchOp::Add(3));
+        assert_eq!((1, 0), (outcome.previous_lane, outcome.current_lane));
+        assert_eq!(20, outcome.previous.recv().await?);
+        assert_eq!(10, outcome.current.recv().await?);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn switch_compare_exchange_only_flips_once() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = diunbounded::<u32>();
+        let receiver2 = receiver.clone();
+        sender.send(10).await?;
+        let first = receiver.switch_compare_exchange(0, 1);
+        let second = receiver2.switch_compare_exchange(0, 1);
+        assert_eq!(10, first.ok().unwrap().recv().await?);
+        // The second consumer sees the channel is already on lane 1 and leaves it there.
+        assert!(second.err().unwrap().is_empty());
+        sender.send(20).await?;
+        assert_eq!(20, receiver2.recv().await?);
+
+        let updated = receiver.switch_update(|lane| Some(lane + 3));
+        assert!(updated.is_ok());
+        sender.send(30).await?;
+        assert_eq!(Ok(30), receiver.get_guard().try_recv());
+        assert!(receiver.switch_update(|_| None).is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn guard_for_lane() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 3, false, true>();
+        receiver.switch_add(2);
+        // A priority producer can always write to lane 0, wherever the channel has switched to.
+        sender.send_to(0)?.send(10).await?;
+        sender.send(20).await?;
+        assert_eq!(10, receiver.recv_from(0)?.recv().await?);
+        assert_eq!(20, receiver.recv().await?);
+        assert!(receiver.guard_for(1)?.is_empty());
+        assert_eq!(err::lane::OutOfRangeError{ lane: 3, lanes: 3 }, sender.guard_for(3).err().unwrap());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn decoupled_double_buffer() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded_decoupled::<usize, 2, true, true>(100);
+        let producer = task::spawn(async move {
+            for i in 0..100usize{
+                sender.send(i).await.unwrap();
+            }
+            sender.switch_xor(1);
+            for i in 100..200usize{
+                sender.send(i).await.unwrap();
+            }
+        });
+        producer.await;
+
+        // The producers filled lane 0 then lane 1, the receiver still hasn't switched.
+        assert_eq!((0..100).collect::<Vec<_>>(), receiver.switch_xor(1).into_iter().collect::<Vec<_>>());
+        assert_eq!((100..200).collect::<Vec<_>>(), receiver.get_guard().into_iter().collect::<Vec<_>>());
+
+        let (sender, receiver) = unbounded_decoupled::<u32, 2, false, true>();
+        receiver.switch_xor(1);
+        sender.send(10).await?;
+        assert!(receiver.is_empty());
+        assert_eq!(10, receiver.recv_from(0)?.recv().await?);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn switch_group_switches_every_channel() -> Result<(), Box<dyn std::error::Error>>{
+        let group = SwitchGroup::<2>::new();
+        let (add_sender, add_receiver) = unbounded_in::<usize, 2, false, true>(&group);
+        let (para_sender, para_receiver) = unbounded_in::<(), 2, false, true>(&group);
+        add_sender.send(1).await?;
+        para_sender.send(()).await?;
+
+        // Draining the add channel flips the para channel to its other lane as well.
+        assert_eq!(vec![1], add_receiver.switch_xor(1).into_iter().collect::<Vec<_>>());
+        assert!(para_receiver.is_empty());
+        para_sender.send(()).await?;
+        assert_eq!((), para_receiver.recv().await?);
+        assert_eq!((), para_receiver.recv_from(0)?.recv().await?);
+        assert_eq!(1, group.lane());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn receiver_stream_follows_active_lane() -> Result<(), Box<dyn std::error::Error>>{
+        use futures::StreamExt;
+
+        let (sender, mut receiver) = unbounded::<u32, 2, false, true>();
+        sender.send(10).await?;
+        sender.send_to(1)?.send(20).await?;
+        assert_eq!(Some(10), receiver.next().await);
+        receiver.switch_xor(1);
+        assert_eq!(Some(20), receiver.next().await);
+
+        let producer = task::spawn(async move {
+            for i in 0..100{
+                sender.send(i).await.unwrap();
+            }
+            sender.close();
+        });
+        let mut received = Vec::new();
+        while let Some(msg) = receiver.next().await{
+            received.push(msg);
+        }
+        producer.await;
+        assert_eq!((0..100).collect::<Vec<_>>(), received);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn pending_receiver_stream_wakes_on_switch() -> Result<(), Box<dyn std::error::Error>>{
+        use futures::StreamExt;
+
+        let (sender, mut receiver) = unbounded::<u32, 2, true, true>();
+        let switcher = task::spawn(async move {
+            task::sleep(std::time::Duration::from_millis(20)).await;
+            // Nothing is ever sent on lane 0, where the stream started waiting.
+            sender.switch_xor(1);
+            sender.send(30).await.unwrap();
+            sender
+        });
+        assert_eq!(Ok(Some(30)), async_std::future::timeout(std::time::Duration::from_secs(1), receiver.next()).await);
+        drop(switcher.await);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn guard_stream_stays_on_its_lane() -> Result<(), Box<dyn std::error::Error>>{
+        use futures::StreamExt;
+
+        let (sender, receiver) = unbounded::<u32, 2, true, true>();
+        for i in 0..10{
+            sender.switch_xor(1).send(i).await?;
+        }
+        sender.close();
+        let evens: Vec<_> = receiver.get_guard().collect().await;
+        let odds: Vec<_> = receiver.guard_for(1)?.map(|i| i * 10).collect().await;
+        assert_eq!(vec![0, 2, 4, 6, 8], evens);
+        assert_eq!(vec![10, 30, 50, 70, 90], odds);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn sender_sink_forwards_and_closes() -> Result<(), Box<dyn std::error::Error>>{
+        use futures::{stream, SinkExt, StreamExt};
+
+        let (sender, receiver) = bounded::<u32, 2, false, true>(4);
+        let producer = task::spawn(async move {
+            stream::iter(0..100).map(Ok).forward(sender).await
+        });
+        // Forwarding closes the sink once the stream is done, which closes every lane.
+        let received: Vec<_> = receiver.clone().collect().await;
+        producer.await?;
+        assert_eq!((0..100).collect::<Vec<_>>(), received);
+        assert!(receiver.is_closed());
+
+        let (mut sender, receiver) = unbounded::<u32, 2, false, true>();
+        sender.send_all(&mut stream::iter(vec![Ok(1), Ok(2)])).await?;
+        assert_eq!(vec![1, 2], receiver.get_guard().into_iter().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn sender_guard_sink_applies_backpressure() -> Result<(), Box<dyn std::error::Error>>{
+        use futures::SinkExt;
+
+        let (sender, receiver) = bounded::<u32, 2, false, true>(1);
+        let mut guard = sender.send_to(1)?;
+        guard.feed(10).await?;
+        // The lane is full, so 20 is held by the sink and it won't take 30 until there's room.
+        guard.feed(20).await?;
+        assert!(guard.feed(30).now_or_never().is_none());
+        assert_eq!(10, receiver.recv_from(1)?.recv().await?);
+        guard.flush().await?;
+        assert_eq!(20, receiver.recv_from(1)?.recv().await?);
+        // Closing the guard closes every lane, not just its own.
+        guard.close().await?;
+        assert!(receiver.recv_from(0)?.is_closed() && receiver.recv_from(1)?.is_closed());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn owned_guard_sink_closes_every_lane() -> Result<(), Box<dyn std::error::Error>>{
+        use futures::SinkExt;
+
+        let (sender, receiver) = bounded::<u32, 2, false, true>(1);
+        let owned = sender.owned_guard_for(1)?;
+        drop(sender);
+        let mut guard = owned.guard();
+        guard.send(10).await?;
+        // The owned guard keeps every lane, so its borrowed guard can close all of them.
+        guard.close().await?;
+        assert!(receiver.recv_from(0)?.is_closed() && receiver.recv_from(1)?.is_closed());
+        assert_eq!(10, receiver.recv_from(1)?.recv().await?);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn sender_sink_takes_messages_that_are_not_send() -> Result<(), Box<dyn std::error::Error>>{
+        use futures::SinkExt;
+        use std::rc::Rc;
+
+        let (mut sender, receiver) = bounded::<Rc<u32>, 1, false, true>(1);
+        sender.feed(Rc::new(10)).await?;
+        // The lane is full, so the sink holds on to 20 until there's room.
+        sender.feed(Rc::new(20)).await?;
+        assert_eq!(Rc::new(10), receiver.recv().await?);
+        SinkExt::close(&mut sender).await?;
+        assert_eq!(Rc::new(20), receiver.recv().await?);
+        assert!(receiver.is_closed());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn send_timeout_hands_message_back() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::{Duration, Instant};
+        use err::send::SendTimeoutError;
+
+        let (sender, receiver) = bounded::<u32, 2, false, true>(1);
+        sender.send_timeout(10, Duration::from_millis(10)).await?;
+        assert_eq!(SendTimeoutError::Timeout(20), sender.send_timeout(20, Duration::from_millis(20)).await.err().unwrap());
+        // Other lanes still have room.
+        sender.send_to(1)?.send_deadline(30, Instant::now()).await?;
+
+        let waiting = async_std::task::spawn(async move {
+            sender.send_timeout(20, Duration::from_secs(10)).await.map(|_| sender)
+        });
+        async_std::task::sleep(Duration::from_millis(20)).await;
+        assert_eq!(10, receiver.recv().await?);
+        let sender = waiting.await?;
+        assert_eq!(20, receiver.recv().await?);
+
+        sender.send(40).await?;
+        let waiting = async_std::task::spawn(async move {
+            sender.send_timeout(50, Duration::from_secs(10)).await
+        });
+        async_std::task::sleep(Duration::from_millis(20)).await;
+        drop(receiver);
+        assert_eq!(SendTimeoutError::Closed(50), waiting.await.err().unwrap());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn select_reports_ready_receiver() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::Duration;
+
+        let (add_sender, add_receiver) = diunbounded::<usize>();
+        let (para_sender, para_receiver) = dibounded::<()>(1);
+        let mut select = Select::new();
+        let add = select.recv(&add_receiver);
+        let para = select.recv(&para_receiver);
+        let priority = select.recv_from(&add_receiver, 1)?;
+        assert!(select.recv_from(&add_receiver, 2).is_err());
+        assert_eq!(None, select.try_ready());
+        assert!(select.ready().now_or_never().is_none());
+        assert_eq!(None, select.ready_timeout(Duration::from_millis(20)).await);
+
+        let sending = async_std::task::spawn(async move {
+            async_std::task::sleep(Duration::from_millis(20)).await;
+            para_sender.send(()).await
+        });
+        assert_eq!(para, select.ready().await);
+        sending.await?;
+        // Selecting doesn't take the message, and the sender being dropped closed the channel.
+        assert_eq!(para, select.ready().await);
+        assert_eq!(Ok(()), para_receiver.try_recv());
+
+        add_sender.send_to(1)?.send(1).await?;
+        assert_eq!(priority, select.ready().await);
+        add_receiver.switch();
+        // Both are ready now, so they take turns.
+        assert_eq!(add, select.ready().await);
+        assert_eq!(para, select.ready().await);
+        assert_eq!(priority, select.ready().await);
+        assert_eq!(Ok(1), add_receiver.try_recv());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn owned_guards_move_into_tasks() -> Result<(), Box<dyn std::error::Error>>{
+        use futures::StreamExt;
+
+        let (sender, receiver) = bounded_decoupled::<u32, 2, true, true>(10);
+        let outcome = sender.switch_outcome_owned(SwitchOp::Xor(1));
+        assert_eq!((0, 1), (outcome.previous.lane(), outcome.current.lane()));
+        let producer = outcome.previous;
+        async_std::task::spawn(async move { producer.send(10).await }).await?;
+
+        // Lane 0 is drained in another task, while lane 1 keeps being used.
+        let drained = receiver.switch_update_owned(|lane| Some(lane + 1)).ok().unwrap();
+        assert_eq!(0, drained.lane());
+        let handle = async_std::task::spawn(drained.collect::<Vec<_>>());
+        sender.send(20).await?;
+        assert_eq!(20, receiver.get_owned_guard().recv().await?);
+        assert!(receiver.owned_guard_for(2).is_err());
+        drop(outcome.current);
+        drop(sender);
+        assert_eq!(vec![10], handle.await);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn senders_dropped_at_once_still_close_the_channel() -> Result<(), Box<dyn std::error::Error>>{
+        use std::sync::Barrier;
+        use std::time::Duration;
+
+        for _ in 0..100{
+            let (sender, receiver) = unbounded::<u32, 2, true, true>();
+            let guard = sender.owned_guard_for(1)?;
+            assert_eq!(2, receiver.sender_count());
+            let barrier = Arc::new(Barrier::new(2));
+            let dropping = std::thread::spawn({
+                let barrier = barrier.clone();
+                move || {
+                    barrier.wait();
+                    drop(guard);
+                }
+            });
+            let mut select = Select::new();
+            let lane = select.recv_from(&receiver, 1)?;
+            barrier.wait();
+            drop(sender);
+            dropping.join().unwrap();
+            // The last of them closed every lane, waking the select up.
+            assert_eq!(lane, async_std::future::timeout(Duration::from_secs(1), select.ready()).await?);
+            assert_eq!(0, receiver.sender_count());
+            assert!(receiver.recv_from(0)?.is_closed());
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn drain_until_switched_away() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::Duration;
+        use futures::StreamExt;
+
+        let (sender, receiver) = diunbounded::<u32>();
+        let guard = receiver.get_owned_guard();
+        let handle = async_std::task::spawn(async move { guard.drain().collect::<Vec<_>>().await });
+        sender.send(1).await?;
+        async_std::task::sleep(Duration::from_millis(20)).await;
+        // The lane is empty but still active, so the drain waits for this one.
+        sender.send(2).await?;
+        async_std::task::sleep(Duration::from_millis(20)).await;
+        let guard = receiver.switch();
+        assert!(!guard.is_active());
+        assert_eq!(vec![1, 2], handle.await);
+
+        sender.send(3).await?;
+        assert!(receiver.get_guard().is_active());
+        // Lane 0 is empty and inactive, so draining it ends straight away.
+        assert_eq!(0, guard.drain().count().await);
+        assert_eq!(vec![3], receiver.switch().into_iter().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn drain_keeps_a_message_sent_right_before_the_switch() -> Result<(), Box<dyn std::error::Error>>{
+        use futures::StreamExt;
+
+        // The send and the switch race the drain, which can find the lane empty just before the message
+        // lands and only then see the switch. It has to take the message either way.
+        for _ in 0..200{
+            let (sender, receiver) = diunbounded::<u32>();
+            let guard = receiver.get_owned_guard();
+            let handle = async_std::task::spawn(async move { guard.drain().collect::<Vec<_>>().await });
+            for msg in 0..100{
+                sender.send(msg).await?;
+            }
+            receiver.switch();
+            assert_eq!((0..100).collect::<Vec<_>>(), handle.await);
+            assert_eq!(0, receiver.guard_for(0)?.into_iter().count());
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn epochs_track_lane_switches() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded::<u32, 2, true, true>(4);
+        let guard = receiver.get_guard();
+        let owned = sender.get_owned_guard();
+        assert_eq!((0, 0), (guard.epoch(), owned.epoch()));
+
+        let outcome = sender.switch_outcome(SwitchOp::Xor(1));
+        assert_eq!((0, 1), (outcome.previous_epoch, outcome.current_epoch));
+        outcome.current.send(1).await?;
+        assert!(outcome.current.is_current());
+        assert!(!guard.is_current() && !owned.is_current());
+
+        // Back on lane 0, which is active again but in a later epoch.
+        receiver.switch_xor(1);
+        assert!(guard.is_active() && !guard.is_current());
+        assert_eq!(2, receiver.epoch());
+        assert!(receiver.get_owned_guard().is_current());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn switched_follows_another_handle() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::Duration;
+
+        let (sender, receiver) = bounded::<u32, 2, true, true>(4);
+        let guard = receiver.get_owned_guard();
+        let follower = receiver.clone();
+        let handle = async_std::task::spawn(async move {
+            let guard = follower.switched().await;
+            (guard.epoch(), guard.recv().await)
+        });
+        async_std::task::sleep(Duration::from_millis(20)).await;
+        sender.switch_outcome(SwitchOp::Xor(1)).current.send(5).await?;
+        assert_eq!((1, Ok(5)), handle.await);
+
+        // The guard was taken before the switch, so it doesn't wait at all.
+        guard.switched().await;
+        assert!(!guard.is_current());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn rotation_after_k_messages() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded::<u32, 2, true, true>(8);
+        let receiver = receiver.with_rotation(Rotation::After(2));
+        for msg in 1..=3{
+            sender.guard_for(0)?.send(msg).await?;
+            sender.guard_for(1)?.send(msg * 10).await?;
+        }
+        let mut received = Vec::new();
+        for _ in 0..6{
+            received.push(receiver.recv().await?);
+        }
+        assert_eq!(vec![1, 2, 10, 20, 3, 30], received);
+        assert_eq!(Err(err::recv::TryRecvError::Empty), receiver.try_recv());
+
+        // Lane 1 is active, but the receive waits on every lane.
+        let handle = async_std::task::spawn(async move {
+            async_std::task::sleep(std::time::Duration::from_millis(20)).await;
+            sender.guard_for(0).unwrap().send(4).await.unwrap();
+        });
+        assert_eq!(Ok(4), receiver.recv().await);
+        handle.await;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn weighted_rotation_delivers_ratios() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<usize, 2, true, true>();
+        let receiver = receiver.with_rotation(Rotation::Weighted(vec![7, 3]));
+        for lane in 0..2{
+            for _ in 0..100{
+                sender.send_to(lane)?.send(lane).await?;
+            }
+        }
+        let mut served = [0; 2];
+        for _ in 0..100{
+            served[receiver.recv().await?] += 1;
+        }
+        assert_eq!([70, 30], served);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn weighted_rotation_needs_a_weight_per_lane(){
+        let (_sender, receiver) = unbounded::<usize, 3, true, true>();
+        receiver.with_rotation(Rotation::Weighted(vec![1, 1]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn weighted_rotation_needs_weights_of_at_least_one(){
+        let (_sender, receiver) = unbounded::<usize, 2, true, true>();
+        receiver.with_rotation(Rotation::Weighted(vec![1, 0]));
+    }
+
+    #[async_std::test]
+    async fn priority_rotation_with_starvation_guard() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<usize, 2, true, true>();
+        let receiver = receiver.with_rotation(Rotation::Priority(StarvationGuard::Messages(2)));
+        for (lane, messages) in [(0, 5), (1, 3)]{
+            for _ in 0..messages{
+                sender.send_to(lane)?.send(lane).await?;
+            }
+        }
+        let mut served = Vec::new();
+        for _ in 0..8{
+            served.push(receiver.recv().await?);
+        }
+        assert_eq!(vec![0, 0, 1, 0, 0, 1, 0, 1], served);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn priority_receives_leave_the_senders_lane_alone() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<usize, 2, true, true>();
+        let receiver = receiver.with_rotation(Rotation::Priority(StarvationGuard::Off));
+        sender.switch_add(1);
+        let epoch = sender.epoch();
+        sender.send_to(0)?.send(0).await?;
+        sender.send(1).await?;
+        assert_eq!(0, receiver.recv().await?);
+        // The sender still sends into lane 1, even though the receiver just served lane 0.
+        sender.send(1).await?;
+        assert_eq!(epoch, sender.epoch());
+        assert_eq!(2, sender.send_to(1)?.len());
+        assert_eq!(1, receiver.recv().await?);
+        assert_eq!(1, receiver.recv().await?);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn starvation_guard_counts_from_a_natural_turn() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<usize, 2, true, true>();
+        let receiver = receiver.with_rotation(Rotation::Priority(StarvationGuard::Messages(2)));
+        sender.send_to(1)?.send(1).await?;
+        assert_eq!(Ok(1), receiver.try_recv());
+        for lane in [0, 0, 0, 1]{
+            sender.send_to(lane)?.send(lane).await?;
+        }
+        let served: Vec<_> = (0..4).map(|_| receiver.try_recv().unwrap()).collect();
+        assert_eq!(vec![0, 0, 1, 0], served);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn time_sliced_rotation() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::Duration;
+
+        let (sender, receiver) = unbounded::<usize, 2, true, true>();
+        let receiver = receiver.with_rotation(Rotation::TimeSlice(Duration::from_millis(20)));
+        for lane in [0, 0, 1, 0]{
+            sender.send_to(lane)?.send(lane).await?;
+        }
+        assert_eq!(Ok(0), receiver.recv().await);
+        async_std::task::sleep(Duration::from_millis(30)).await;
+        assert_eq!(Ok(1), receiver.recv().await);
+        assert_eq!(Ok(0), receiver.recv().await);
+        assert_eq!(Ok(0), receiver.recv().await);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn time_slices_run_on_the_timer() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::Duration;
+
+        let (sender, receiver) = unbounded::<usize, 2, true, true>();
+        let receiver = receiver.with_rotation(Rotation::TimeSlice(Duration::from_millis(100)));
+        // Nobody receives, but the lane still moves on once its slice is up.
+        async_std::task::sleep(Duration::from_millis(150)).await;
+        assert_eq!(1, sender.get_owned_guard().lane());
+        // The timer stops with the last receiver that rotates.
+        let clone = receiver.clone();
+        drop(receiver);
+        drop(clone);
+        async_std::task::sleep(Duration::from_millis(200)).await;
+        assert_eq!(1, sender.get_owned_guard().lane());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn dyn_lanes_from_configuration() -> Result<(), Box<dyn std::error::Error>>{
+        let lanes = 5;
+        let (sender, receiver) = dyn_unbounded::<usize, true, true>(lanes);
+        assert_eq!(lanes, receiver.lanes());
+        // Like the fixed size channels, the switch wraps around the number of lanes.
+        sender.switch_add(lanes + 3);
+        sender.send(3).await?;
+        assert_eq!(Ok(3), receiver.recv_from(3)?.recv().await);
+        assert!(sender.guard_for(lanes).is_err());
+
+        let receiver = receiver.with_rotation(Rotation::OnEmpty);
+        for lane in (0..lanes).rev(){
+            sender.send_to(lane)?.send(lane).await?;
+        }
+        let mut received = Vec::new();
+        for _ in 0..lanes{
+            received.push(receiver.recv().await?);
+        }
+        assert_eq!(vec![3, 4, 0, 1, 2], received);
+
+        drop(sender);
+        assert_eq!(Err(err::recv::RecvError), receiver.recv().await);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn dyn_channel_needs_a_lane(){
+        dyn_bounded::<usize, true, true>(0, 1);
+    }
+
+    #[async_std::test]
+    async fn add_and_retire_lanes_live() -> Result<(), Box<dyn std::error::Error>>{
+        use err::lane::{OutOfRangeError, RetireError};
+
+        let (sender, receiver) = dyn_unbounded::<usize, true, true>(2);
+        sender.send_to(1)?.send(10).await?;
+        assert_eq!(2, receiver.add_lane());
+        sender.switch_add(2);
+        sender.send(20).await?;
+        let retired = sender.send_to(1)?;
+        let epoch = receiver.epoch();
+
+        // Lane 2 moves down to take the retired lane's place, and stays active.
+        sender.retire_lane(1, 0).await?;
+        assert_eq!(2, receiver.lanes());
+        assert_ne!(epoch, receiver.epoch());
+        assert!(retired.send(11).await.is_err());
+        assert_eq!(Ok(10), receiver.recv_from(0)?.try_recv());
+        assert_eq!(Ok(20), receiver.try_recv());
+
+        // Retiring the active lane switches to the lane it's redirected to, messages and all.
+        sender.send(30).await?;
+        assert!(receiver.retire_lane(1, 0)?.is_empty());
+        assert_eq!(Ok(30), receiver.try_recv());
+        assert_eq!(Err(RetireError::SameLane(0)), receiver.retire_lane(0, 0));
+        assert_eq!(Err(RetireError::OutOfRange(OutOfRangeError{ lane: 1, lanes: 1 })), receiver.retire_lane(1, 0));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn guards_follow_their_lane_as_lanes_come_and_go() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = dyn_unbounded::<u32, true, true>(4);
+        sender.switch_add(3);
+        let active = receiver.get_guard();
+        let owned = receiver.get_owned_guard();
+        let first = receiver.guard_for(0)?;
+        // Lane 3 moves down to position 2, but it's still the same lane, and still active.
+        sender.retire_lane(1, 0).await?;
+        assert!(active.is_active() && owned.is_active());
+        assert!(!first.is_active());
+
+        // A guard taken while there were 4 lanes doesn't mistake lane 4 for lane 0.
+        let (sender, receiver) = dyn_unbounded::<u32, true, true>(4);
+        let first = receiver.guard_for(0)?;
+        assert_eq!(4, sender.add_lane());
+        sender.switch_add(4);
+        assert_eq!(4, receiver.get_owned_guard().lane());
+        assert!(!first.is_active());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn receiver_retires_into_a_full_lane_without_waiting() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = dyn_bounded::<u32, true, true>(2, 1);
+        sender.send(0).await?;
+        sender.send_to(1)?.send(1).await?;
+        // The lane at `to` is full, so the leftover is handed back instead of waiting for room.
+        assert_eq!(vec![0], receiver.retire_lane(0, 1)?);
+        assert_eq!(1, receiver.lanes());
+        assert_eq!(Ok(1), receiver.try_recv());
+        assert_eq!(Err(err::recv::TryRecvError::Empty), receiver.try_recv());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn send_timeout_follows_retired_lane() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::Duration;
+
+        let (sender, receiver) = dyn_bounded::<u32, true, true>(1, 1);
+        sender.send(1).await?;
+        receiver.add_lane();
+        let handle = async_std::task::spawn(async move {
+            async_std::task::sleep(Duration::from_millis(20)).await;
+            assert!(receiver.retire_lane(0, 1).unwrap().is_empty());
+            assert_eq!(Ok(1), receiver.recv().await);
+            receiver
+        });
+        // The wait starts on the full lane 0, and carries on in lane 1 once lane 0 is retired.
+        sender.send_timeout(2, Duration::from_secs(5)).await?;
+        let receiver = handle.await;
+        assert_eq!(Ok(2), receiver.try_recv());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn waiting_receiver_follows_retired_lane() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = dyn_bounded::<u32, true, true>(1, 1);
+        sender.add_lane();
+        let handle = async_std::task::spawn(async move { receiver.recv().await });
+        async_std::task::sleep(std::time::Duration::from_millis(20)).await;
+        sender.retire_lane(0, 1).await?;
+        sender.send(5).await?;
+        assert_eq!(Ok(5), handle.await);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn lanes_with_their_own_capacity() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded_with::<u32, 2, true, true, _>([1, 3]);
+        assert_eq!(Some(1), receiver.capacity());
+        sender.send(10).await?;
+        assert!(sender.is_full());
+        assert!(sender.try_send(11).is_err());
+        sender.switch_add(1);
+        assert_eq!(Some(3), sender.capacity());
+        for val in 20..23{
+            sender.try_send(val)?;
+        }
+        assert!(sender.is_full());
+        assert_eq!(Some(1), receiver.recv_from(0)?.capacity());
+
+        // Bounded and unbounded lanes can be mixed.
+        let (sender, receiver) = bounded_with::<u32, 2, true, true, _>([Some(1), None]);
+        assert_eq!((Some(1), None), (sender.send_to(0)?.capacity(), sender.send_to(1)?.capacity()));
+        for val in 0..100{
+            sender.send_to(1)?.try_send(val)?;
+        }
+        assert!(!receiver.recv_from(1)?.is_full());
+        assert_eq!(100, sender.send_to(1)?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn switch_loop_never_panics(){
+        let (sender, receiver) = bounded::<u32, 3, true, true>(1);
+        for val in (0..10_000).chain(usize::MAX - 10..=usize::MAX){
+            receiver.switch_add(val);
+            receiver.switch_sub(val);
+            receiver.switch_and(val);
+            receiver.switch_max(val);
+            receiver.switch_min(val);
+            receiver.switch_nand(val);
+            receiver.switch_or(val);
+            receiver.switch_xor(val);
+            sender.switch_add(val);
+            sender.switch_sub(val);
+            sender.switch_nand(val);
+            sender.switch_max(val);
+        }
+    }
+
     async fn add_switch_loop(value: &mut usize, add: Result<usize, err::recv::RecvError>, add_receiver: &SwitchReceiver<usize, 2, true>){
         if let Ok(add) = add{
             *value += add
@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+use async_std::channel::Receiver;
+use event_listener::EventListener;
+use crate::err::lane::OutOfRangeError;
+use crate::ready::Ready;
+use super::SwitchReceiver;
+use super::lane::LaneEvents;
+use super::wait::wait_any;
+
+struct LaneReady<'a, T>{
+    receiver: &'a Receiver<T>,
+    events: &'a LaneEvents,
+}
+
+impl<'a, T> Ready for LaneReady<'a, T>{
+    fn is_ready(&self) -> bool{
+        !self.receiver.is_empty() || self.receiver.is_closed()
+    }
+
+    fn listen(&self, listeners: &mut Vec<EventListener>){
+        listeners.push(self.events.listen_ready());
+    }
+}
+
+impl<T, const N: usize, const P: bool> Ready for SwitchReceiver<T, N, P>{
+    fn is_ready(&self) -> bool{
+        let receiver = &self.receivers[self.count.lane(N)];
+        !receiver.is_empty() || receiver.is_closed()
+    }
+
+    fn listen(&self, listeners: &mut Vec<EventListener>){
+        // The active lane can change while waiting, so listen to all of them, and to the switch itself.
+        for events in self.events.iter(){
+            listeners.push(events.listen_ready());
+        }
+        listeners.push(self.count.listen());
+    }
+}
+
+/// Waits until one of several switch receivers, or specific lanes of them, is ready.
+///
+/// A receive is ready when it wouldn't have to wait, because there is a message or the lane is closed.
+/// Nothing is received, so another handle can still take the message before the ready receiver gets to it.
+///
+/// A receiver that follows the active lane is checked again whenever one of its lanes gets a message
+/// or the channel switches lanes.
+pub struct Select<'a>{
+    ops: Vec<Box<dyn Ready + Send + Sync + 'a>>,
+    // Where the next check starts, so a busy receiver can't starve the others.
+    next: usize,
+}
+
+impl<'a> Select<'a>{
+    pub fn new() -> Self{
+        Self{
+            ops: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// Add a receive from whichever lane of `receiver` is active, returning the index of the operation.
+    pub fn recv<T: Send, const N: usize, const P: bool>(&mut self, receiver: &'a SwitchReceiver<T, N, P>) -> usize{
+        self.ops.push(Box::new(receiver));
+        self.ops.len() - 1
+    }
+
+    /// Add a receive from `lane` of `receiver`, returning the index of the operation.
+    pub fn recv_from<T: Send, const N: usize, const P: bool>(&mut self, receiver: &'a SwitchReceiver<T, N, P>, lane: usize) -> Result<usize, OutOfRangeError>{
+        if lane >= N{
+            return Err(OutOfRangeError{ lane, lanes: N });
+        }
+        self.ops.push(Box::new(LaneReady{
+            receiver: &receiver.receivers[lane],
+            events: &receiver.events[lane],
+        }));
+        Ok(self.ops.len() - 1)
+    }
+
+    /// Get the index of a ready operation, if there is one.
+    pub fn try_ready(&mut self) -> Option<usize>{
+        let count = self.ops.len();
+        let index = (0..count)
+            .map(|offset| (self.next + offset) % count)
+            .find(|&index| self.ops[index].is_ready())?;
+        self.next = (index + 1) % count;
+        Some(index)
+    }
+
+    /// Wait until an operation is ready, and get its index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no operations have been added.
+    pub async fn ready(&mut self) -> usize{
+        self.ready_until(None).await.unwrap()
+    }
+
+    /// Wait until an operation is ready, giving up after `timeout`.
+    pub async fn ready_timeout(&mut self, timeout: Duration) -> Option<usize>{
+        // A timeout too far in the future is the same as no timeout at all.
+        self.ready_until(Instant::now().checked_add(timeout)).await
+    }
+
+    /// Wait until an operation is ready, giving up at `deadline`.
+    pub async fn ready_deadline(&mut self, deadline: Instant) -> Option<usize>{
+        self.ready_until(Some(deadline)).await
+    }
+
+    async fn ready_until(&mut self, deadline: Option<Instant>) -> Option<usize>{
+        assert!(!self.ops.is_empty(), "no operations have been added to `Select`");
+        loop{
+            // Start listening before checking, so nothing that happens in between is missed.
+            let mut listeners = Vec::new();
+            for op in self.ops.iter(){
+                op.listen(&mut listeners);
+            }
+            if let Some(index) = self.try_ready(){
+                return Some(index);
+            }
+            if !wait_any(&mut listeners, deadline).await{
+                return self.try_ready();
+            }
+        }
+    }
+}
+
+impl<'a> Default for Select<'a>{
+    fn default() -> Self{
+        Self::new()
+    }
+}
@@ -1,35 +1,71 @@
-use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
-use async_std::channel::Receiver;
-use crate::{PERMITTED};
-use crate::{Switcher, err::recv::{RecvError, TryRecvError}};
+use std::sync::Arc;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use async_std::channel::{Receiver, Recv};
+use event_listener::EventListener;
+use futures_core::Stream;
+use crate::{Lanes, PERMITTED, Position, Rotation, SwitchCounter, SwitchOp, SwitchOutcome, SwitchState, switch_lane, update_lane};
+use crate::{Switcher, err::lane::OutOfRangeError, err::recv::{RecvError, TryRecvError}};
+use crate::lane_table::Handles;
+use super::lane::{LaneEvents, OwnedEvents, close_lanes};
+use crate::rotation::Rotator;
+use super::time_slice::rotator;
+use super::wait::wait_for_epoch;
 
 pub struct SwitchReceiver<T, const N: usize, const P: bool>{
-    pub(crate) count: Arc<AtomicUsize>,
+    pub(crate) count: Arc<SwitchCounter>,
     pub(crate) receivers: [Receiver<T>; N],
+    pub(crate) events: Arc<[LaneEvents; N]>,
+    pub(crate) handles: Arc<Handles>,
+    pub(crate) rotation: Option<Rotator>,
+    // Kept by the stream while it waits, so a switch made by another handle wakes it up.
+    pub(crate) switched: Option<EventListener>,
 }
 
 impl<T, const N: usize, const P: bool> SwitchReceiver<T, N, P>{
     /// Try to receive from the activate channel.
+    ///
+    /// With a rotation set, this moves on past empty lanes, so it only fails if every lane is empty.
     pub fn try_recv(&self) -> Result<T, TryRecvError>{
-        Ok(self.receivers[self.count.load(Ordering::SeqCst) % N].try_recv()?)
+        match &self.rotation{
+            Some(rotator) => rotator.try_recv(&self.count, N, |lane| {
+                let msg = self.receivers[lane].try_recv()?;
+                self.events[lane].received();
+                Ok(msg)
+            }),
+            None => self.get_guard().try_recv(),
+        }
     }
 
     /// receive from the activate channel.
+    ///
+    /// With a rotation set, this waits for a message on any lane.
     pub async fn recv(&'_ self) -> Result<T, RecvError>{
-        Ok(self.receivers[self.count.load(Ordering::SeqCst) % N].recv().await?)
+        if self.rotation.is_none(){
+            return self.get_guard().recv().await;
+        }
+        loop{
+            // Start listening before trying, so a message sent in between isn't missed.
+            let mut listeners: Vec<_> = self.events.iter().map(|events| events.listen_ready()).collect();
+            match self.try_recv(){
+                Ok(msg) => return Ok(msg),
+                Err(TryRecvError::Closed) => return Err(RecvError),
+                Err(TryRecvError::Empty) => {},
+            }
+            std::future::poll_fn(|cx| {
+                if listeners.iter_mut().any(|listener| Pin::new(listener).poll(cx).is_ready()){
+                    Poll::Ready(())
+                }else{
+                    Poll::Pending
+                }
+            }).await;
+        }
     }
 
     /// Close all the channels.
     pub fn close(&self) -> bool{
-        // Any number of threads could call close,
-        // but only one will get a false value from
-        // the first call of receiver close.
-        for receiver in self.receivers.iter(){
-            if receiver.close(){
-                return true;
-            }
-        }
-        return false
+        close_lanes(&self.receivers, &self.events[..])
     }
 
     /// Checks if all the channels have been closed.
@@ -39,51 +75,183 @@ impl<T, const N: usize, const P: bool> SwitchReceiver<T, N, P>{
 
     /// Check if the activate channel is empty.
     pub fn is_empty(&self) -> bool{
-        self.receivers[self.count.load(Ordering::SeqCst) % N].is_empty()
+        self.receivers[self.count.lane(N)].is_empty()
     }
 
     /// Check if the activate channel is full, unbounded channels are never full.
     pub fn is_full(&self) -> bool{
-        self.receivers[self.count.load(Ordering::SeqCst) % N].is_full()
+        self.receivers[self.count.lane(N)].is_full()
     }
 
+    /// Returns the capacity of the active lane, `None` if it's unbounded.
     pub fn capacity(&self) -> Option<usize>{
-        // These are only allowed be constructed with uniform capacity,
-        // so you can just get the capacity from any instance.
-        self.receivers[0].capacity()
+        self.receivers[self.count.lane(N)].capacity()
     }
 
     /// Returns the number of senders for the channel.
     pub fn sender_count(&self) -> usize{
-        self.receivers[0].sender_count()
+        self.handles.sender_count()
     }
 
     /// Returns the number of receivers for the channel.
     pub fn receiver_count(&self) -> usize{
-        self.receivers[0].receiver_count()
+        self.handles.receiver_count()
+    }
+
+    /// Returns the epoch the channel is in, which moves on every time the channel switches to a different lane.
+    pub fn epoch(&self) -> usize{
+        self.count.epoch()
     }
 
     pub fn get_guard(&self) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.load(Ordering::SeqCst) % N]
+        self.guard(self.count.state(N))
+    }
+
+    /// Wait until another handle switches the channel to a different lane, returning a guard for the new lane.
+    ///
+    /// Only switches after the call are waited for, a guard's `switched` also catches
+    /// the ones since the guard was taken.
+    pub async fn switched(&self) -> SwitchReceiverGuard<'_, T>{
+        self.get_guard().switched().await;
+        self.get_guard()
+    }
+
+    /// Get a guard for `lane` without switching the channel.
+    pub fn guard_for(&self, lane: usize) -> Result<SwitchReceiverGuard<'_, T>, OutOfRangeError>{
+        if lane < N{
+            Ok(self.guard(SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: N })
+        }
+    }
+
+    /// The same as `guard_for`, so a specific lane can be used as `receiver.recv_from(lane)?.recv()`.
+    pub fn recv_from(&self, lane: usize) -> Result<SwitchReceiverGuard<'_, T>, OutOfRangeError>{
+        self.guard_for(lane)
+    }
+
+    fn guard(&self, state: SwitchState) -> SwitchReceiverGuard<'_, T>{
+        SwitchReceiverGuard::new(&self.receivers[state.lane], &self.events[state.lane], &self.handles, Position{ count: &self.count, state, lanes: Lanes::Fixed(N) })
+    }
+
+    /// Like `get_guard`, but the guard keeps its own handle on the lane, so it can be moved into a spawned task.
+    pub fn get_owned_guard(&self) -> OwnedSwitchReceiverGuard<T>{
+        self.owned_guard(self.count.state(N))
+    }
+
+    /// Like `guard_for`, but the guard keeps its own handle on the lane, so it can be moved into a spawned task.
+    pub fn owned_guard_for(&self, lane: usize) -> Result<OwnedSwitchReceiverGuard<T>, OutOfRangeError>{
+        if lane < N{
+            Ok(self.owned_guard(SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: N })
+        }
+    }
+
+    fn owned_guard(&self, state: SwitchState) -> OwnedSwitchReceiverGuard<T>{
+        self.handles.add_receiver();
+        OwnedSwitchReceiverGuard{
+            receiver: self.receivers[state.lane].clone(),
+            events: OwnedEvents::Fixed(Arc::new(self.receivers.clone()), self.events.clone(), self.handles.clone()),
+            state,
+            count: self.count.clone(),
         }
     }
 } 
 
-#[derive(Clone)]
+impl<T, const N: usize> SwitchReceiver<T, N, PERMITTED>{
+    /// Let the receiver switch lanes by itself according to `rotation`, whenever it receives from the active lane.
+    ///
+    /// Panics if `rotation` is `Weighted` with a different number of weights than lanes, or a weight of 0.
+    /// Only the receiver's own `try_recv` and `recv` rotate, guards and the `Stream` impl don't.
+    /// Clones rotate the same way, but count their messages separately.
+    /// A `TimeSlice` rotates on the async timer, even while nobody receives.
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self{
+        self.rotation = Some(rotator(rotation, &self.count, || N));
+        self
+    }
+
+    /// Like `switch_outcome`, but with owned guards.
+    pub fn switch_outcome_owned(&self, op: SwitchOp) -> SwitchOutcome<OwnedSwitchReceiverGuard<T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state| self.owned_guard(state))
+    }
+
+    /// Like `switch_update`, but with owned guards.
+    pub fn switch_update_owned<F: FnMut(usize) -> Option<usize>>(&self, f: F) -> Result<OwnedSwitchReceiverGuard<T>, OwnedSwitchReceiverGuard<T>>{
+        let guard = |state: SwitchState| self.owned_guard(state);
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
+
 pub struct SwitchReceiverGuard<'a, T>{
-    receiver: &'a Receiver<T>
+    receiver: &'a Receiver<T>,
+    events: &'a LaneEvents,
+    handles: &'a Handles,
+    position: Position<'a>,
+    // The pending receive while the guard is being polled as a stream.
+    recv: Option<Recv<'a, T>>,
 }
 
 impl<'a, T> SwitchReceiverGuard<'a, T>{
+    pub(crate) fn new(receiver: &'a Receiver<T>, events: &'a LaneEvents, handles: &'a Handles, position: Position<'a>) -> Self{
+        Self{
+            receiver,
+            events,
+            handles,
+            position,
+            recv: None,
+        }
+    }
+
+    /// Check if the guard's lane is still the active lane of the channel.
+    pub fn is_active(&self) -> bool{
+        self.position.is_active()
+    }
+
+    /// The epoch the channel was in when the guard was taken.
+    pub fn epoch(&self) -> usize{
+        self.position.state.epoch
+    }
+
+    /// Check if the channel hasn't switched lanes since the guard was taken.
+    ///
+    /// Unlike `is_active`, this is `false` once the channel has switched away, even if it has switched back since.
+    pub fn is_current(&self) -> bool{
+        self.position.is_current()
+    }
+
+    /// Wait until the channel has switched lanes since the guard was taken, finishing straight away if it already has.
+    pub async fn switched(&self){
+        wait_for_epoch(self.position.count, self.position.state.epoch).await
+    }
+
+    /// Receive from the guard's lane until it's empty and no longer the active lane.
+    ///
+    /// While the lane is still active the stream waits for more messages,
+    /// so messages sent just before a switch aren't left behind.
+    /// Once the channel has switched away, it ends as soon as the lane is empty.
+    pub fn drain(&self) -> SwitchReceiverGuardDrain<'a, T>{
+        SwitchReceiverGuardDrain{
+            receiver: self.receiver,
+            events: self.events,
+            position: self.position,
+            recv: None,
+            switched: None,
+        }
+    }
+
     /// Try to receive from the activate channel.
     pub fn try_recv(&self) -> Result<T, TryRecvError>{
-        Ok(self.receiver.try_recv()?)
+        let msg = self.receiver.try_recv()?;
+        self.events.received();
+        Ok(msg)
     }
 
     /// receive from the activate channel.
     pub async fn recv(&'_ self) -> Result<T, RecvError>{
-        Ok(self.receiver.recv().await?)
+        let msg = self.receiver.recv().await?;
+        self.events.received();
+        Ok(msg)
     }
 
     /// Checks if all the channels have been closed.
@@ -102,19 +270,17 @@ impl<'a, T> SwitchReceiverGuard<'a, T>{
     }
 
     pub fn capacity(&self) -> Option<usize>{
-        // These are only allowed be constructed with uniform capacity,
-        // so you can just get the capacity from any instance.
         self.receiver.capacity()
     }
 
     /// Returns the number of senders for the channel.
     pub fn sender_count(&self) -> usize{
-        self.receiver.sender_count()
+        self.handles.sender_count()
     }
 
     /// Returns the number of receivers for the channel.
     pub fn receiver_count(&self) -> usize{
-        self.receiver.receiver_count()
+        self.handles.receiver_count()
     }
 } 
 
@@ -124,72 +290,247 @@ impl<'a, T> std::iter::IntoIterator for SwitchReceiverGuard<'a, T>{
     
     fn into_iter(self) -> <Self as std::iter::IntoIterator>::IntoIter { 
         SwitchReceiverGuardIterator{
-            receiver: self.receiver
+            receiver: self.receiver,
+            events: self.events,
         }
     }
 }
 
-pub struct SwitchReceiverGuardIterator<'a, T>{
-    receiver: &'a Receiver<T>,
+impl<'a, T> Clone for SwitchReceiverGuard<'a, T>{
+    fn clone(&self) -> Self{
+        Self::new(self.receiver, self.events, self.handles, self.position)
+    }
 }
 
-impl<'a, T> std::iter::Iterator for SwitchReceiverGuardIterator<'a, T>{
-
+/// Receives from the lane the guard was taken for, ending once that lane is closed and empty.
+impl<'a, T> Stream for SwitchReceiverGuard<'a, T>{
     type Item = T;
-    fn next(&mut self) -> std::option::Option<<Self as std::iter::Iterator>::Item> { 
-        self.receiver.try_recv().ok()
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>>{
+        let receiver = self.receiver;
+        let recv = self.recv.get_or_insert_with(|| receiver.recv());
+        let msg = futures_core::ready!(Pin::new(recv).poll(cx));
+        self.recv = None;
+        if msg.is_ok(){
+            self.events.received();
+        }
+        Poll::Ready(msg.ok())
     }
 }
 
+/// A `SwitchReceiverGuard` that isn't tied to the lifetime of its `SwitchReceiver`.
+///
+/// It counts as a receiver of its lane until it's dropped.
+pub struct OwnedSwitchReceiverGuard<T>{
+    pub(crate) receiver: Receiver<T>,
+    pub(crate) events: OwnedEvents<T, Receiver<T>>,
+    pub(crate) state: SwitchState,
+    pub(crate) count: Arc<SwitchCounter>,
+}
 
-impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchReceiver<T, N, PERMITTED>{
-    type Output = SwitchReceiverGuard<'a, T>;
+impl<T> OwnedSwitchReceiverGuard<T>{
+    /// Borrow the guard as a `SwitchReceiverGuard`.
+    pub fn guard(&self) -> SwitchReceiverGuard<'_, T>{
+        let position = Position{ count: &self.count, state: self.state, lanes: self.events.lanes() };
+        SwitchReceiverGuard::new(&self.receiver, self.events.get(self.state.lane), self.events.handles(), position)
+    }
 
-    fn switch_add(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_add(val, Ordering::SeqCst)]
-        }
+    /// Check if the guard's lane is still the active lane of the channel.
+    pub fn is_active(&self) -> bool{
+        self.guard().is_active()
     }
 
-    fn switch_and(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_and(val, Ordering::SeqCst)]
-        }
+    /// Check if the channel hasn't switched lanes since the guard was taken.
+    pub fn is_current(&self) -> bool{
+        self.guard().is_current()
     }
 
-    fn switch_max(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_max(val, Ordering::SeqCst)]
-        }
+    /// Wait until the channel has switched lanes since the guard was taken, like `SwitchReceiverGuard::switched`.
+    pub async fn switched(&self){
+        self.guard().switched().await
+    }
+
+    /// Receive from the guard's lane until it's empty and no longer the active lane, like `SwitchReceiverGuard::drain`.
+    pub fn drain(&self) -> SwitchReceiverGuardDrain<'_, T>{
+        self.guard().drain()
+    }
+
+    /// The lane this guard receives from.
+    pub fn lane(&self) -> usize{
+        self.state.lane
+    }
+
+    /// The epoch the channel was in when the guard was taken.
+    pub fn epoch(&self) -> usize{
+        self.state.epoch
+    }
+
+    /// Try to receive from the activate channel.
+    pub fn try_recv(&self) -> Result<T, TryRecvError>{
+        self.guard().try_recv()
+    }
+
+    /// receive from the activate channel.
+    pub async fn recv(&'_ self) -> Result<T, RecvError>{
+        self.guard().recv().await
+    }
+
+    /// Checks if all the channels have been closed.
+    pub fn is_closed(&self) -> bool{
+        self.receiver.is_closed()
+    }
+
+    /// Check if the activate channel is empty.
+    pub fn is_empty(&self) -> bool{
+        self.receiver.is_empty()
+    }
+
+    /// Check if the activate channel is full, unbounded channels are never full.
+    pub fn is_full(&self) -> bool{
+        self.receiver.is_full()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.receiver.capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.events.handles().sender_count()
     }
 
-    fn switch_min(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_min(val, Ordering::SeqCst)]
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.events.handles().receiver_count()
+    }
+}
+
+impl<T> Clone for OwnedSwitchReceiverGuard<T>{
+    fn clone(&self) -> Self{
+        self.events.handles().add_receiver();
+        Self{
+            receiver: self.receiver.clone(),
+            events: self.events.clone(),
+            state: self.state,
+            count: self.count.clone(),
         }
     }
+}
 
-    fn switch_nand(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_nand(val, Ordering::SeqCst)]
+impl<T> Drop for OwnedSwitchReceiverGuard<T>{
+    fn drop(&mut self){
+        if self.events.handles().remove_receiver(){
+            self.events.close();
         }
     }
+}
+
+/// Receives from the lane the guard was taken for, ending once that lane is closed and empty.
+impl<T> Stream for OwnedSwitchReceiverGuard<T>{
+    type Item = T;
 
-    fn switch_or(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_or(val, Ordering::SeqCst)]
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>>{
+        let msg = futures_core::ready!(Pin::new(&mut self.receiver).poll_next(cx));
+        if msg.is_some(){
+            self.events.get(self.state.lane).received();
         }
+        Poll::Ready(msg)
     }
+}
+
+pub struct SwitchReceiverGuardDrain<'a, T>{
+    receiver: &'a Receiver<T>,
+    events: &'a LaneEvents,
+    position: Position<'a>,
+    recv: Option<Recv<'a, T>>,
+    switched: Option<EventListener>,
+}
+
+impl<'a, T> Stream for SwitchReceiverGuardDrain<'a, T>{
+    type Item = T;
 
-    fn switch_sub(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_sub(val, Ordering::SeqCst)]
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>>{
+        loop{
+            // Start listening before checking, so a switch in between isn't missed.
+            if self.switched.is_none(){
+                self.switched = Some(self.position.count.listen());
+            }
+            // Check for the switch before polling, so a message sent just before it is still received.
+            let active = self.position.is_active();
+            let receiver = self.receiver;
+            let recv = self.recv.get_or_insert_with(|| receiver.recv());
+            if let Poll::Ready(msg) = Pin::new(recv).poll(cx){
+                self.recv = None;
+                if msg.is_ok(){
+                    self.events.received();
+                }
+                return Poll::Ready(msg.ok());
+            }
+            if !active{
+                self.recv = None;
+                return Poll::Ready(None);
+            }
+            let switched = self.switched.as_mut().unwrap();
+            futures_core::ready!(Pin::new(switched).poll(cx));
+            self.switched = None;
         }
     }
+}
 
-    fn switch_xor(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_xor(val, Ordering::SeqCst)]
+pub struct SwitchReceiverGuardIterator<'a, T>{
+    pub(crate) receiver: &'a Receiver<T>,
+    pub(crate) events: &'a LaneEvents,
+}
+
+impl<'a, T> std::iter::Iterator for SwitchReceiverGuardIterator<'a, T>{
+
+    type Item = T;
+    fn next(&mut self) -> std::option::Option<<Self as std::iter::Iterator>::Item> { 
+        let msg = self.receiver.try_recv().ok()?;
+        self.events.received();
+        Some(msg)
+    }
+}
+
+
+impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchReceiver<T, N, PERMITTED>{
+    type Output = SwitchReceiverGuard<'a, T>;
+
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<SwitchReceiverGuard<'a, T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state| self.guard(state))
+    }
+
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<SwitchReceiverGuard<'a, T>, SwitchReceiverGuard<'a, T>>{
+        let guard = |state: SwitchState| self.guard(state);
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
+
+/// Receives from whichever lane is active, ending once the active lane is closed and empty.
+///
+/// A pending stream also wakes up when another handle switches lanes, and carries on with the new lane.
+impl<T, const N: usize, const P: bool> Stream for SwitchReceiver<T, N, P>{
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>>{
+        let this = &mut *self;
+        loop{
+            // Start listening before reading the lane, so a switch made in between isn't missed.
+            let switched = match &mut this.switched{
+                Some(listener) => listener,
+                None => this.switched.insert(this.count.listen()),
+            };
+            let lane = this.count.lane(N);
+            if let Poll::Ready(msg) = Pin::new(&mut this.receivers[lane]).poll_next(cx){
+                if msg.is_some(){
+                    this.events[lane].received();
+                }
+                return Poll::Ready(msg);
+            }
+            match Pin::new(switched).poll(cx){
+                Poll::Ready(()) => this.switched = None,
+                Poll::Pending => return Poll::Pending,
+            }
         }
     }
 }
@@ -212,10 +553,26 @@ impl From<async_std::channel::RecvError> for RecvError{
 impl<T, const N: usize, const P: bool> Clone for SwitchReceiver<T, N, P>{
     fn clone(&self) -> Self{
         use std::convert::TryInto;
-        let receivers: Vec<Receiver<T>> = self.receivers.iter().map(|r| r.clone()).collect();
+        let receivers: Vec<Receiver<T>> = self.receivers.iter().cloned().collect();
+        self.handles.add_receiver();
         Self{
             count: self.count.clone(),
             receivers: receivers.try_into().unwrap(),
+            events: self.events.clone(),
+            handles: self.handles.clone(),
+            rotation: self.rotation.clone(),
+            switched: None,
+        }
+    }
+}
+
+impl<T, const N: usize, const P: bool> Drop for SwitchReceiver<T, N, P>{
+    fn drop(&mut self){
+        // async_std only closes a lane once its last receiver is gone, which is after this runs.
+        // Close them here instead, so senders waiting for room get woken up to find out.
+        // The handles are counted separately, so two receivers dropped at once can't both miss being the last.
+        if self.handles.remove_receiver(){
+            self.close();
         }
     }
 }
\ No newline at end of file
@@ -1,79 +1,229 @@
-use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
-use async_std::channel::Sender;
-use crate::{PERMITTED};
-use crate::{Switcher, err::send::{SendError, TrySendError}};
+use std::sync::{Arc, Mutex};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use async_std::channel::{Send, Sender};
+use event_listener::EventListener;
+use futures_sink::Sink;
+use crate::{Lanes, PERMITTED, Position, SwitchCounter, SwitchOp, SwitchOutcome, SwitchState, switch_lane, update_lane};
+use crate::{Switcher, err::lane::OutOfRangeError, err::send::{SendError, SendTimeoutError, TrySendError}};
+use crate::lane_table::Handles;
+use super::lane::{AllLanes, LaneEvents, OwnedEvents, close_lanes};
+use super::wait::wait_for_epoch;
+
+/// A message the sink is waiting to put into a full lane.
+struct PendingSend<T>{
+    lane: usize,
+    msg: T,
+    // Started before the last try, so room made in between isn't missed.
+    space: Option<EventListener>,
+}
 
 pub struct SwitchSender<T, const N: usize, const P: bool>{
-    pub(crate) count: Arc<AtomicUsize>,
+    pub(crate) count: Arc<SwitchCounter>,
     pub(crate) senders: [Sender<T>; N],
+    pub(crate) events: Arc<[LaneEvents; N]>,
+    pub(crate) handles: Arc<Handles>,
+    // A message that didn't fit into a full lane while being used as a sink.
+    // This is only ever accessed through `&mut self`, the mutex just keeps the sender `Sync`
+    // while the listener is in there.
+    pending: Mutex<Option<PendingSend<T>>>,
 }
 
 impl<T, const N: usize, const P: bool> SwitchSender<T, N, P>{
+    pub(crate) fn new(count: Arc<SwitchCounter>, senders: [Sender<T>; N], events: Arc<[LaneEvents; N]>, handles: Arc<Handles>) -> Self{
+        Self{
+            count,
+            senders,
+            events,
+            handles,
+            pending: Mutex::new(None),
+        }
+    }
+
     pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>>{
-        Ok(self.senders[self.count.load(Ordering::SeqCst) % N].try_send(msg)?)
+        self.get_guard().try_send(msg)
     }
 
     pub async fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
-        Ok(self.senders[self.count.load(Ordering::SeqCst) % N].send(msg).await?)
+        self.get_guard().send(msg).await
+    }
+
+    /// Send into the activate channel, handing the message back if it's still full after `timeout`.
+    pub async fn send_timeout(&'_ self, msg: T, timeout: Duration) -> Result<(), SendTimeoutError<T>>{
+        self.get_guard().send_timeout(msg, timeout).await
+    }
+
+    /// Send into the activate channel, handing the message back if it's still full at `deadline`.
+    pub async fn send_deadline(&'_ self, msg: T, deadline: Instant) -> Result<(), SendTimeoutError<T>>{
+        self.get_guard().send_deadline(msg, deadline).await
     }
 
     pub fn close(&self) -> bool{
-        // Any number of threads could call close,
-        // but only one will get a false value from
-        // the first call of reciever close.
-        for reciever in self.senders.iter(){
-            if reciever.close(){
-                return true;
-            }
-        }
-        return false
+        close_lanes(&self.senders, &self.events[..])
     }
 
     pub fn is_closed(&self) -> bool{
         self.senders[0].is_closed()
     }
 
+    pub fn is_empty(&self) -> bool{
+        self.senders[self.count.lane(N)].is_empty()
+    }
+
     pub fn is_full(&self) -> bool{
-        self.senders[self.count.load(Ordering::SeqCst) % N].is_full()
+        self.senders[self.count.lane(N)].is_full()
     }
 
     pub fn len(&self) -> usize{
-        self.senders[self.count.load(Ordering::SeqCst) % N].len()
+        self.senders[self.count.lane(N)].len()
     }
 
+    /// Returns the capacity of the active lane, `None` if it's unbounded.
     pub fn capacity(&self) -> Option<usize>{
-        self.senders[0].capacity()
+        self.senders[self.count.lane(N)].capacity()
     }
 
     /// Returns the number of senders for the channel.
     pub fn sender_count(&self) -> usize{
-        self.senders[0].sender_count()
+        self.handles.sender_count()
     }
 
     /// Returns the number of receivers for the channel.
     pub fn receiver_count(&self) -> usize{
-        self.senders[0].receiver_count()
+        self.handles.receiver_count()
+    }
+
+    /// Returns the epoch the channel is in, which moves on every time the channel switches to a different lane.
+    pub fn epoch(&self) -> usize{
+        self.count.epoch()
+    }
+
+    fn get_guard(&self) -> SwitchSenderGuard<'_, T>{
+        self.guard(self.count.state(N))
+    }
+
+    /// Wait until another handle switches the channel to a different lane, returning a guard for the new lane.
+    pub async fn switched(&self) -> SwitchSenderGuard<'_, T>{
+        wait_for_epoch(&self.count, self.count.epoch()).await;
+        self.get_guard()
+    }
+
+    /// Get a guard for `lane` without switching the channel.
+    pub fn guard_for(&self, lane: usize) -> Result<SwitchSenderGuard<'_, T>, OutOfRangeError>{
+        if lane < N{
+            Ok(self.guard(SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: N })
+        }
+    }
+
+    /// The same as `guard_for`, so a specific lane can be used as `sender.send_to(lane)?.send(msg)`.
+    pub fn send_to(&self, lane: usize) -> Result<SwitchSenderGuard<'_, T>, OutOfRangeError>{
+        self.guard_for(lane)
+    }
+
+    fn guard(&self, state: SwitchState) -> SwitchSenderGuard<'_, T>{
+        let lanes = AllLanes::Fixed(&self.senders, &self.events[..]);
+        SwitchSenderGuard::new(&self.senders[state.lane], &self.events[state.lane], lanes, &self.handles, Position{ count: &self.count, state, lanes: Lanes::Fixed(N) })
+    }
+
+    /// Get a guard for the active lane that keeps its own handle on the lane, so it can be moved into a spawned task.
+    pub fn get_owned_guard(&self) -> OwnedSwitchSenderGuard<T>{
+        self.owned_guard(self.count.state(N))
+    }
+
+    /// Like `guard_for`, but the guard keeps its own handle on the lane, so it can be moved into a spawned task.
+    pub fn owned_guard_for(&self, lane: usize) -> Result<OwnedSwitchSenderGuard<T>, OutOfRangeError>{
+        if lane < N{
+            Ok(self.owned_guard(SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: N })
+        }
+    }
+
+    fn owned_guard(&self, state: SwitchState) -> OwnedSwitchSenderGuard<T>{
+        self.handles.add_sender();
+        OwnedSwitchSenderGuard{
+            sender: self.senders[state.lane].clone(),
+            events: OwnedEvents::Fixed(Arc::new(self.senders.clone()), self.events.clone(), self.handles.clone()),
+            state,
+            count: self.count.clone(),
+        }
+    }
+}
+
+impl<T, const N: usize> SwitchSender<T, N, PERMITTED>{
+    /// Like `switch_outcome`, but with owned guards.
+    pub fn switch_outcome_owned(&self, op: SwitchOp) -> SwitchOutcome<OwnedSwitchSenderGuard<T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state| self.owned_guard(state))
+    }
+
+    /// Like `switch_update`, but with owned guards.
+    pub fn switch_update_owned<F: FnMut(usize) -> Option<usize>>(&self, f: F) -> Result<OwnedSwitchSenderGuard<T>, OwnedSwitchSenderGuard<T>>{
+        let guard = |state: SwitchState| self.owned_guard(state);
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
     }
 }
 
-#[derive(Clone)]
 pub struct SwitchSenderGuard<'a, T>{
-    sender: &'a Sender<T>
+    sender: &'a Sender<T>,
+    events: &'a LaneEvents,
+    // Closed when the guard is closed as a sink.
+    lanes: AllLanes<'a, T>,
+    handles: &'a Handles,
+    position: Position<'a>,
+    // The pending send while the guard is being used as a sink.
+    send: Option<Send<'a, T>>,
 }
 
 impl<'a, T> SwitchSenderGuard<'a, T>{
+    pub(crate) fn new(sender: &'a Sender<T>, events: &'a LaneEvents, lanes: AllLanes<'a, T>, handles: &'a Handles, position: Position<'a>) -> Self{
+        Self{
+            sender,
+            events,
+            lanes,
+            handles,
+            position,
+            send: None,
+        }
+    }
+
     pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>>{
-        Ok(self.sender.try_send(msg)?)
+        self.sender.try_send(msg)?;
+        self.events.sent();
+        Ok(())
     }
 
     pub async fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
-        Ok(self.sender.send(msg).await?)
+        self.sender.send(msg).await?;
+        self.events.sent();
+        Ok(())
+    }
+
+    /// Send into the channel, handing the message back if it's still full after `timeout`.
+    pub async fn send_timeout(&'_ self, msg: T, timeout: Duration) -> Result<(), SendTimeoutError<T>>{
+        match Instant::now().checked_add(timeout){
+            Some(deadline) => self.send_deadline(msg, deadline).await,
+            // Too far in the future to ever time out.
+            None => Ok(self.send(msg).await?),
+        }
+    }
+
+    /// Send into the channel, handing the message back if it's still full at `deadline`.
+    pub async fn send_deadline(&'_ self, msg: T, deadline: Instant) -> Result<(), SendTimeoutError<T>>{
+        self.events.send_deadline(self.sender, msg, deadline).await
     }
 
     pub fn is_closed(&self) -> bool{
         self.sender.is_closed()
     }
 
+    pub fn is_empty(&self) -> bool{
+        self.sender.is_empty()
+    }
+
     pub fn is_full(&self) -> bool{
         self.sender.is_full()
     }
@@ -88,67 +238,139 @@ impl<'a, T> SwitchSenderGuard<'a, T>{
 
     /// Returns the number of senders for the channel.
     pub fn sender_count(&self) -> usize{
-        self.sender.sender_count()
+        self.handles.sender_count()
     }
 
     /// Returns the number of receivers for the channel.
     pub fn receiver_count(&self) -> usize{
-        self.sender.receiver_count()
+        self.handles.receiver_count()
+    }
+
+    /// The epoch the channel was in when the guard was taken.
+    pub fn epoch(&self) -> usize{
+        self.position.state.epoch
+    }
+
+    /// Check if the channel hasn't switched lanes since the guard was taken.
+    pub fn is_current(&self) -> bool{
+        self.position.is_current()
     }
 }
 
-impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchSender<T, N, PERMITTED>{
-    type Output = SwitchSenderGuard<'a, T>;
+/// A `SwitchSenderGuard` that isn't tied to the lifetime of its `SwitchSender`.
+///
+/// It counts as a sender of its lane until it's dropped.
+pub struct OwnedSwitchSenderGuard<T>{
+    pub(crate) sender: Sender<T>,
+    pub(crate) events: OwnedEvents<T, Sender<T>>,
+    pub(crate) state: SwitchState,
+    pub(crate) count: Arc<SwitchCounter>,
+}
 
-    fn switch_add(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_add(val, Ordering::SeqCst)]
-        }
+impl<T> OwnedSwitchSenderGuard<T>{
+    /// Borrow the guard as a `SwitchSenderGuard`.
+    pub fn guard(&self) -> SwitchSenderGuard<'_, T>{
+        let position = Position{ count: &self.count, state: self.state, lanes: self.events.lanes() };
+        SwitchSenderGuard::new(&self.sender, self.events.get(self.state.lane), self.events.all_lanes(), self.events.handles(), position)
     }
 
-    fn switch_and(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_and(val, Ordering::SeqCst)]
-        }
+    /// The lane this guard sends into.
+    pub fn lane(&self) -> usize{
+        self.state.lane
     }
 
-    fn switch_max(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_max(val, Ordering::SeqCst)]
-        }
+    /// The epoch the channel was in when the guard was taken.
+    pub fn epoch(&self) -> usize{
+        self.state.epoch
     }
 
-    fn switch_min(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_min(val, Ordering::SeqCst)]
-        }
+    /// Check if the channel hasn't switched lanes since the guard was taken.
+    pub fn is_current(&self) -> bool{
+        self.guard().is_current()
     }
 
-    fn switch_nand(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_nand(val, Ordering::SeqCst)]
-        }
+    pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>>{
+        self.guard().try_send(msg)
     }
 
-    fn switch_or(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_or(val, Ordering::SeqCst)]
-        }
+    pub async fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
+        self.guard().send(msg).await
     }
 
-    fn switch_sub(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_sub(val, Ordering::SeqCst)]
+    /// Send into the channel, handing the message back if it's still full after `timeout`.
+    pub async fn send_timeout(&'_ self, msg: T, timeout: Duration) -> Result<(), SendTimeoutError<T>>{
+        self.guard().send_timeout(msg, timeout).await
+    }
+
+    /// Send into the channel, handing the message back if it's still full at `deadline`.
+    pub async fn send_deadline(&'_ self, msg: T, deadline: Instant) -> Result<(), SendTimeoutError<T>>{
+        self.guard().send_deadline(msg, deadline).await
+    }
+
+    pub fn is_closed(&self) -> bool{
+        self.sender.is_closed()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.sender.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.sender.is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.sender.len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.sender.capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.events.handles().sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.events.handles().receiver_count()
+    }
+}
+
+impl<T> Clone for OwnedSwitchSenderGuard<T>{
+    fn clone(&self) -> Self{
+        self.events.handles().add_sender();
+        Self{
+            sender: self.sender.clone(),
+            events: self.events.clone(),
+            state: self.state,
+            count: self.count.clone(),
         }
     }
+}
 
-    fn switch_xor(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_xor(val, Ordering::SeqCst)]
+impl<T> Drop for OwnedSwitchSenderGuard<T>{
+    fn drop(&mut self){
+        if self.events.handles().remove_sender(){
+            self.events.close();
         }
     }
 }
 
+impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchSender<T, N, PERMITTED>{
+    type Output = SwitchSenderGuard<'a, T>;
+
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<SwitchSenderGuard<'a, T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state| self.guard(state))
+    }
+
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<SwitchSenderGuard<'a, T>, SwitchSenderGuard<'a, T>>{
+        let guard = |state: SwitchState| self.guard(state);
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
+
 impl<T> From<async_std::channel::SendError<T>> for SendError<T>{
     fn from(err: async_std::channel::SendError<T>) -> Self { 
         Self(err.0)
@@ -166,11 +388,122 @@ impl<T> From<async_std::channel::TrySendError<T>> for TrySendError<T>{
 
 impl<T, const N: usize, const P: bool> Clone for SwitchSender<T, N, P>{
     fn clone(&self) -> Self{
-        use std::convert::TryInto;
-        let senders: Vec<Sender<T>> = self.senders.iter().map(|s| s.clone()).collect();
-        Self{
-            count: self.count.clone(),
-            senders: senders.try_into().unwrap(),
+        self.handles.add_sender();
+        Self::new(self.count.clone(), self.senders.clone(), self.events.clone(), self.handles.clone())
+    }
+}
+
+impl<T, const N: usize, const P: bool> Drop for SwitchSender<T, N, P>{
+    fn drop(&mut self){
+        // async_std only closes a lane once its last sender is gone, which is after this runs.
+        // Close them here instead, so selects waiting on the receivers get woken up to find out.
+        // The handles are counted separately, so two senders dropped at once can't both miss being the last.
+        if self.handles.remove_sender(){
+            self.close();
+        }
+    }
+}
+
+impl<'a, T> Clone for SwitchSenderGuard<'a, T>{
+    fn clone(&self) -> Self{
+        Self::new(self.sender, self.events, self.lanes, self.handles, self.position)
+    }
+}
+
+// A pending sink message is only ever moved, never pinned, so the sender doesn't need `T: Unpin`.
+impl<T, const N: usize, const P: bool> Unpin for SwitchSender<T, N, P>{}
+
+/// Sends into whichever lane is active when each message is started.
+///
+/// A message that doesn't fit into a full lane is held until that lane has room,
+/// and the sink isn't ready for another message until then. Closing the sink closes every lane.
+impl<T, const N: usize, const P: bool> Sink<T> for SwitchSender<T, N, P>{
+    type Error = SendError<T>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError<T>>>{
+        self.poll_flush(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: T) -> Result<(), SendError<T>>{
+        let this = self.get_mut();
+        let state = this.count.state(N);
+        let lane = state.lane;
+        match this.guard(state).try_send(msg){
+            Ok(()) => Ok(()),
+            Err(TrySendError::Closed(msg)) => Err(SendError(msg)),
+            Err(TrySendError::Full(msg)) => {
+                *this.pending.get_mut().unwrap() = Some(PendingSend{ lane, msg, space: None });
+                Ok(())
+            },
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError<T>>>{
+        let this = self.get_mut();
+        let pending = this.pending.get_mut().unwrap();
+        while let Some(PendingSend{ lane, msg, space }) = pending.take(){
+            if let Some(mut space) = space{
+                if Pin::new(&mut space).poll(cx).is_pending(){
+                    *pending = Some(PendingSend{ lane, msg, space: Some(space) });
+                    return Poll::Pending;
+                }
+            }
+            let space = this.events[lane].listen_space();
+            match this.senders[lane].try_send(msg){
+                Ok(()) => {
+                    this.events[lane].sent();
+                    return Poll::Ready(Ok(()));
+                },
+                Err(async_std::channel::TrySendError::Closed(msg)) => return Poll::Ready(Err(SendError(msg))),
+                Err(async_std::channel::TrySendError::Full(msg)) => *pending = Some(PendingSend{ lane, msg, space: Some(space) }),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError<T>>>{
+        futures_core::ready!(self.as_mut().poll_flush(cx))?;
+        self.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Sends into the lane the guard was taken for, waiting for room while that lane is full.
+///
+/// Closing the sink closes every lane of the channel, like closing a `SwitchSender` does,
+/// even for a guard borrowed from an `OwnedSwitchSenderGuard`.
+impl<'a, T> Sink<T> for SwitchSenderGuard<'a, T>{
+    type Error = SendError<T>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError<T>>>{
+        self.poll_flush(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, msg: T) -> Result<(), SendError<T>>{
+        match self.try_send(msg){
+            Ok(()) => Ok(()),
+            Err(TrySendError::Closed(msg)) => Err(SendError(msg)),
+            Err(TrySendError::Full(msg)) => {
+                self.send = Some(self.sender.send(msg));
+                Ok(())
+            },
         }
     }
-}
\ No newline at end of file
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError<T>>>{
+        if let Some(send) = self.send.as_mut(){
+            let result = futures_core::ready!(Pin::new(send).poll(cx));
+            self.send = None;
+            result?;
+            self.events.sent();
+            return Poll::Ready(Ok(()));
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError<T>>>{
+        futures_core::ready!(self.as_mut().poll_flush(cx))?;
+        self.lanes.close();
+        Poll::Ready(Ok(()))
+    }
+}
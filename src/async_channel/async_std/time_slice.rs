@@ -0,0 +1,56 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::task::Poll;
+use std::time::Duration;
+use event_listener::Event;
+use crate::{Rotation, SwitchCounter};
+use crate::rotation::{Rotator, rotate};
+
+/// Stops the time slice timer once the last rotator holding on to it is dropped.
+struct StopTimer(Arc<Event>);
+
+impl Drop for StopTimer{
+    fn drop(&mut self){
+        self.0.notify(usize::MAX);
+    }
+}
+
+/// A rotator for `rotation` on a channel with `lanes()` lanes.
+///
+/// For a `TimeSlice`, this spawns a task that rotates `count` on the async timer
+/// whenever a lane has been active for a whole slice, until the rotator and its clones are dropped.
+pub(crate) fn rotator(rotation: Rotation, count: &Arc<SwitchCounter>, lanes: impl Fn() -> usize + Send + 'static) -> Rotator{
+    let rotator = Rotator::new(rotation.clone(), lanes());
+    let slice = match rotation{
+        Rotation::TimeSlice(slice) => slice,
+        _ => return rotator,
+    };
+    let stop = Arc::new(Event::new());
+    let timer = Arc::new(StopTimer(stop.clone()));
+    async_std::task::spawn(time_slices(count.clone(), lanes, slice, Arc::downgrade(&timer), stop));
+    rotator.with_timer(timer)
+}
+
+async fn time_slices(count: Arc<SwitchCounter>, lanes: impl Fn() -> usize, slice: Duration, timer: Weak<StopTimer>, stop: Arc<Event>){
+    loop{
+        // Start listening before checking, so neither a switch nor the timer being dropped is missed.
+        let mut stopped = stop.listen();
+        let mut switched = count.listen();
+        if timer.strong_count() == 0{
+            return;
+        }
+        let state = count.state(lanes());
+        let interrupted = std::future::poll_fn(|cx| {
+            if Pin::new(&mut stopped).poll(cx).is_ready() || Pin::new(&mut switched).poll(cx).is_ready(){
+                Poll::Ready(())
+            }else{
+                Poll::Pending
+            }
+        });
+        // A switch starts the slice over, otherwise the lane has had its slice.
+        if async_std::future::timeout(slice, interrupted).await.is_err(){
+            rotate(&count, lanes(), state);
+        }
+    }
+}
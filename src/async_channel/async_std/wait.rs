@@ -0,0 +1,39 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::Instant;
+use event_listener::EventListener;
+use crate::SwitchCounter;
+
+/// Wait until any of `listeners` is notified, returning `false` if `deadline` passes first.
+pub(crate) async fn wait_any(listeners: &mut [EventListener], deadline: Option<Instant>) -> bool{
+    let notified = std::future::poll_fn(|cx| {
+        if listeners.iter_mut().any(|listener| Pin::new(listener).poll(cx).is_ready()){
+            Poll::Ready(())
+        }else{
+            Poll::Pending
+        }
+    });
+    match deadline{
+        None => {
+            notified.await;
+            true
+        },
+        Some(deadline) => {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            async_std::future::timeout(timeout, notified).await.is_ok()
+        },
+    }
+}
+
+/// Wait until `count` has moved on from `epoch`.
+pub(crate) async fn wait_for_epoch(count: &SwitchCounter, epoch: usize){
+    loop{
+        // Start listening before checking, so a switch in between isn't missed.
+        let listener = count.listen();
+        if count.epoch() != epoch{
+            return;
+        }
+        listener.await;
+    }
+}
@@ -1,2 +1,4 @@
 #[cfg(feature = "async_std")]
-pub mod async_std;
\ No newline at end of file
+pub mod async_std;
+#[cfg(feature = "tokio")]
+pub mod tokio;
@@ -0,0 +1,203 @@
+use std::sync::{Arc, Mutex};
+use event_listener::Event;
+use ::tokio::sync::mpsc;
+use crate::err::{recv::TryRecvError, send::{SendError, TrySendError}};
+
+/// A bounded lane with room for `cap` messages.
+pub(crate) fn bounded<T>(cap: usize) -> (LaneSender<T>, ReceiverLane<T>){
+    let (sender, receiver) = mpsc::channel(cap);
+    lane(MpscSender::Bounded(sender), LaneReceiver::Bounded(receiver))
+}
+
+pub(crate) fn unbounded<T>() -> (LaneSender<T>, ReceiverLane<T>){
+    let (sender, receiver) = mpsc::unbounded_channel();
+    lane(MpscSender::Unbounded(sender), LaneReceiver::Unbounded(receiver))
+}
+
+fn lane<T>(sender: MpscSender<T>, receiver: LaneReceiver<T>) -> (LaneSender<T>, ReceiverLane<T>){
+    let sent = Arc::new(Event::new());
+    (
+        LaneSender{ sender, sent: sent.clone() },
+        ReceiverLane{ receiver: Mutex::new(receiver), sent },
+    )
+}
+
+/// tokio has separate types for bounded and unbounded channels,
+/// so this lets a switch channel treat both the same way.
+enum MpscSender<T>{
+    Bounded(mpsc::Sender<T>),
+    Unbounded(mpsc::UnboundedSender<T>),
+}
+
+/// A single lane of a tokio switch channel on the sending side.
+pub(crate) struct LaneSender<T>{
+    sender: MpscSender<T>,
+    // Shared with the receiving lane, see `ReceiverLane`.
+    sent: Arc<Event>,
+}
+
+impl<T> LaneSender<T>{
+    pub(crate) fn try_send(&self, msg: T) -> Result<(), TrySendError<T>>{
+        match &self.sender{
+            MpscSender::Bounded(sender) => sender.try_send(msg)?,
+            MpscSender::Unbounded(sender) => sender.send(msg).map_err(|err| TrySendError::Closed(err.0))?,
+        }
+        self.sent.notify_additional(1);
+        Ok(())
+    }
+
+    pub(crate) async fn send(&self, msg: T) -> Result<(), SendError<T>>{
+        match &self.sender{
+            MpscSender::Bounded(sender) => sender.send(msg).await?,
+            MpscSender::Unbounded(sender) => sender.send(msg)?,
+        }
+        self.sent.notify_additional(1);
+        Ok(())
+    }
+
+    pub(crate) fn is_closed(&self) -> bool{
+        match &self.sender{
+            MpscSender::Bounded(sender) => sender.is_closed(),
+            MpscSender::Unbounded(sender) => sender.is_closed(),
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool{
+        match &self.sender{
+            MpscSender::Bounded(sender) => sender.capacity() == 0,
+            MpscSender::Unbounded(_) => false,
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> Option<usize>{
+        match &self.sender{
+            MpscSender::Bounded(sender) => Some(sender.max_capacity()),
+            MpscSender::Unbounded(_) => None,
+        }
+    }
+
+    pub(crate) fn sender_count(&self) -> usize{
+        match &self.sender{
+            MpscSender::Bounded(sender) => sender.strong_count(),
+            MpscSender::Unbounded(sender) => sender.strong_count(),
+        }
+    }
+}
+
+impl<T> Clone for LaneSender<T>{
+    fn clone(&self) -> Self{
+        let sender = match &self.sender{
+            MpscSender::Bounded(sender) => MpscSender::Bounded(sender.clone()),
+            MpscSender::Unbounded(sender) => MpscSender::Unbounded(sender.clone()),
+        };
+        Self{
+            sender,
+            sent: self.sent.clone(),
+        }
+    }
+}
+
+impl<T> Drop for LaneSender<T>{
+    fn drop(&mut self){
+        // The last sender going away closes the lane, which waiting receives have to find out about.
+        self.sent.notify(usize::MAX);
+    }
+}
+
+enum LaneReceiver<T>{
+    Bounded(mpsc::Receiver<T>),
+    Unbounded(mpsc::UnboundedReceiver<T>),
+}
+
+/// A single lane of a tokio switch channel on the receiving side.
+///
+/// tokio receivers need `&mut self` to receive, so the lane keeps its receiver behind a lock
+/// that is only held for a `try_recv`. tokio also only wakes the last task that polled a receive,
+/// so receives wait on an event the senders notify instead, and any number of them can wait at once.
+pub(crate) struct ReceiverLane<T>{
+    receiver: Mutex<LaneReceiver<T>>,
+    // Notified whenever a message is sent, or the lane is closed.
+    sent: Arc<Event>,
+}
+
+impl<T> ReceiverLane<T>{
+    pub(crate) fn try_recv(&self) -> Result<T, TryRecvError>{
+        match &mut *self.receiver.lock().unwrap(){
+            LaneReceiver::Bounded(receiver) => Ok(receiver.try_recv()?),
+            LaneReceiver::Unbounded(receiver) => Ok(receiver.try_recv()?),
+        }
+    }
+
+    /// Receive from the lane, or `None` once it's closed and empty.
+    pub(crate) async fn recv(&self) -> Option<T>{
+        loop{
+            // Start listening before trying, so a message sent in between isn't missed.
+            let listener = self.sent.listen();
+            match self.try_recv(){
+                Ok(msg) => return Some(msg),
+                Err(TryRecvError::Closed) => return None,
+                Err(TryRecvError::Empty) => listener.await,
+            }
+        }
+    }
+
+    /// Closes the lane, returning `true` if it wasn't already closed.
+    pub(crate) fn close(&self) -> bool{
+        let closed = match &mut *self.receiver.lock().unwrap(){
+            LaneReceiver::Bounded(receiver) => {
+                let closed = receiver.is_closed();
+                receiver.close();
+                closed
+            },
+            LaneReceiver::Unbounded(receiver) => {
+                let closed = receiver.is_closed();
+                receiver.close();
+                closed
+            },
+        };
+        self.sent.notify(usize::MAX);
+        !closed
+    }
+
+    pub(crate) fn is_closed(&self) -> bool{
+        match &*self.receiver.lock().unwrap(){
+            LaneReceiver::Bounded(receiver) => receiver.is_closed(),
+            LaneReceiver::Unbounded(receiver) => receiver.is_closed(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool{
+        match &*self.receiver.lock().unwrap(){
+            LaneReceiver::Bounded(receiver) => receiver.is_empty(),
+            LaneReceiver::Unbounded(receiver) => receiver.is_empty(),
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool{
+        match &*self.receiver.lock().unwrap(){
+            LaneReceiver::Bounded(receiver) => receiver.len() == receiver.max_capacity(),
+            LaneReceiver::Unbounded(_) => false,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize{
+        match &*self.receiver.lock().unwrap(){
+            LaneReceiver::Bounded(receiver) => receiver.len(),
+            LaneReceiver::Unbounded(receiver) => receiver.len(),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> Option<usize>{
+        match &*self.receiver.lock().unwrap(){
+            LaneReceiver::Bounded(receiver) => Some(receiver.max_capacity()),
+            LaneReceiver::Unbounded(_) => None,
+        }
+    }
+
+    pub(crate) fn sender_count(&self) -> usize{
+        match &*self.receiver.lock().unwrap(){
+            LaneReceiver::Bounded(receiver) => receiver.sender_strong_count(),
+            LaneReceiver::Unbounded(receiver) => receiver.sender_strong_count(),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+mod lane;
+mod switch_receiver;
+mod switch_sender;
+mod diswitchchannel;
+
+use std::convert::TryInto;
+use crate::SwitchCounter;
+use std::sync::{Arc, Weak};
+use core::iter::repeat_with;
+use lane::{LaneSender, ReceiverLane};
+use crate::err::send::{SendError, TrySendError};
+use crate::err::recv::TryRecvError;
+pub use switch_receiver::{SwitchReceiver, SwitchReceiverGuard, SwitchReceiverGuardIterator};
+pub use switch_sender::{SwitchSender, SwitchSenderGuard};
+pub use diswitchchannel::{dibounded, diunbounded, DiSwitchSender, DiSwitchReceiver};
+
+
+pub fn bounded<T, const N: usize, const S: bool, const P: bool>(cap: usize) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    let (senders, receivers): (Vec<LaneSender<T>>, Vec<ReceiverLane<T>>) = repeat_with(|| lane::bounded(cap))
+        .take(N)
+        .unzip();
+
+    from_lanes(senders, receivers)
+}
+
+pub fn unbounded<T, const N: usize, const S: bool, const P: bool>() -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    let (senders, receivers): (Vec<LaneSender<T>>, Vec<ReceiverLane<T>>) = repeat_with(lane::unbounded)
+        .take(N)
+        .unzip();
+
+    from_lanes(senders, receivers)
+}
+
+fn from_lanes<T, const N: usize, const S: bool, const P: bool>(senders: Vec<LaneSender<T>>, receivers: Vec<ReceiverLane<T>>) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    let receivers: Arc<[ReceiverLane<T>; N]> = Arc::new(receivers.try_into().unwrap_or_else(|_| unreachable!()));
+
+    let switch = Arc::new(SwitchCounter::new());
+    (
+        SwitchSender{
+            count: switch.clone(),
+            senders: senders.try_into().unwrap_or_else(|_| unreachable!()),
+            receivers: Arc::downgrade(&receivers) as Weak<[ReceiverLane<T>]>,
+        },
+        SwitchReceiver{
+            count: switch,
+            receivers,
+        }
+    )
+}
+
+impl<T> From<::tokio::sync::mpsc::error::SendError<T>> for SendError<T>{
+    fn from(err: ::tokio::sync::mpsc::error::SendError<T>) -> Self {
+        Self(err.0)
+    }
+}
+
+impl<T> From<::tokio::sync::mpsc::error::TrySendError<T>> for TrySendError<T>{
+    fn from(err: ::tokio::sync::mpsc::error::TrySendError<T>) -> Self {
+        match err{
+            ::tokio::sync::mpsc::error::TrySendError::Full(t) => Self::Full(t),
+            ::tokio::sync::mpsc::error::TrySendError::Closed(t) => Self::Closed(t),
+        }
+    }
+}
+
+impl From<::tokio::sync::mpsc::error::TryRecvError> for TryRecvError{
+    fn from(err: ::tokio::sync::mpsc::error::TryRecvError) -> Self {
+        match err{
+            ::tokio::sync::mpsc::error::TryRecvError::Empty => Self::Empty,
+            ::tokio::sync::mpsc::error::TryRecvError::Disconnected => Self::Closed,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use crate::*;
+    use super::*;
+
+    #[test]
+    fn constructors(){
+        let (_sender, _receiver) = unbounded::<u32, 10, false, false>();
+        let (_sender, _receiver) = unbounded::<u32, 10, false, true>();
+        let (_sender, _receiver) = unbounded::<u32, 10, true, false>();
+        let (_sender, _receiver) = unbounded::<u32, 10, true, true>();
+        let (_sender, _receiver) = bounded::<u32, 10, false, false>(10);
+        let (_sender, _receiver) = bounded::<u32, 10, false, true>(10);
+        let (_sender, _receiver) = bounded::<u32, 10, true, false>(10);
+        let (_sender, _receiver) = bounded::<u32, 10, true, true>(10);
+    }
+
+    #[tokio::test]
+    async fn unbounded_send_receive() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 1, false, false>();
+        sender.send(10).await?;
+        assert_eq!(10, receiver.recv().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bounded_introspection() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded::<u32, 2, false, false>(1);
+        assert!(receiver.is_empty());
+        assert_eq!(Some(1), receiver.capacity());
+        assert_eq!(Some(1), sender.capacity());
+        sender.send(10).await?;
+        assert!(!receiver.is_empty());
+        assert!(receiver.is_full());
+        assert!(sender.is_full());
+        assert_eq!(1, sender.len());
+        assert!(matches!(sender.try_send(20), Err(err::send::TrySendError::Full(20))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn guard_introspection() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded::<u32, 2, PERMITTED, false>(2);
+        sender.send(10).await?;
+        let outcome = sender.switch_outcome(SwitchOp::Add(1));
+        assert_eq!(1, outcome.previous.len());
+        assert!(!outcome.previous.is_empty());
+        assert!(outcome.current.is_empty());
+        assert_eq!(1, outcome.current.receiver_count());
+        drop(receiver);
+        assert_eq!(0, outcome.previous.len());
+        assert_eq!(0, outcome.current.receiver_count());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unbounded_switch_send_receive() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 2, true, false>();
+        sender.switch_add(1).send(10).await?;
+        assert_eq!(err::recv::TryRecvError::Empty, receiver.try_recv().err().unwrap());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unbounded_send_switch_receive() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 2, false, true>();
+        sender.send(10).await?;
+        assert_eq!(10, receiver.switch_add(1).try_recv().ok().unwrap());
+        assert_eq!(err::recv::TryRecvError::Empty, receiver.try_recv().err().unwrap());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unbounded_switch_send_switch_receive() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 2, true, true>();
+        // This switches both the sender and receiver, and sends 10 on the previous channel
+        sender.switch_add(1).send(10).await?;
+        // This sends 20 on the current channel
+        sender.send(20).await?;
+        // This recieves 20 on the current channel, and switches back to the previous one
+        assert_eq!(20, receiver.switch_add(1).try_recv().ok().unwrap());
+        // This recieves the 10 we originally sent
+        assert_eq!(10, receiver.try_recv().ok().unwrap());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sender_close_drains_then_ends() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = diunbounded::<u32>();
+        sender.send(10).await?;
+        assert!(sender.close());
+        assert!(!sender.close());
+        assert!(sender.is_closed());
+        assert!(receiver.is_closed());
+        assert!(sender.send(20).await.is_err());
+        assert_eq!(10, receiver.recv().await?);
+        assert!(receiver.recv().await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn close_wakes_pending_recv(){
+        let (sender, receiver) = diunbounded::<u32>();
+        let handle = ::tokio::spawn(async move { receiver.recv().await });
+        ::tokio::task::yield_now().await;
+        sender.close();
+        assert!(handle.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn concurrent_recvs_are_all_woken(){
+        let (sender, receiver) = diunbounded::<u32>();
+        let receiver = Arc::new(receiver);
+        let first = ::tokio::spawn({
+            let receiver = receiver.clone();
+            async move { receiver.recv().await }
+        });
+        let second = ::tokio::spawn({
+            let receiver = receiver.clone();
+            async move { receiver.recv().await }
+        });
+        ::tokio::task::yield_now().await;
+        sender.send(1).await.unwrap();
+        sender.send(2).await.unwrap();
+        let mut received = [first.await.unwrap().unwrap(), second.await.unwrap().unwrap()];
+        received.sort_unstable();
+        assert_eq!(received, [1, 2]);
+    }
+
+    struct NoClone;
+
+    #[test]
+    fn clone_test(){
+        let (sender, _receiver) = diunbounded::<NoClone>();
+        let _sender2 = sender.clone();
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+use crate::{PERMITTED, SwitchCounter, SwitchOp, SwitchOutcome, SwitchState, switch_lane, update_lane};
+use crate::{Switcher, err::recv::{RecvError, TryRecvError}};
+use super::lane::ReceiverLane;
+
+/// tokio channels only allow a single consumer, so unlike the
+/// async_std `SwitchReceiver` this can't be cloned.
+pub struct SwitchReceiver<T, const N: usize, const P: bool>{
+    pub(crate) count: Arc<SwitchCounter>,
+    pub(crate) receivers: Arc<[ReceiverLane<T>; N]>,
+}
+
+impl<T, const N: usize, const P: bool> SwitchReceiver<T, N, P>{
+    /// Try to receive from the activate channel.
+    pub fn try_recv(&self) -> Result<T, TryRecvError>{
+        self.receivers[self.count.lane(N)].try_recv()
+    }
+
+    /// receive from the activate channel.
+    pub async fn recv(&'_ self) -> Result<T, RecvError>{
+        self.receivers[self.count.lane(N)].recv().await.ok_or(RecvError)
+    }
+
+    /// Close all the channels.
+    pub fn close(&self) -> bool{
+        let mut closed = false;
+        for receiver in self.receivers.iter(){
+            closed |= receiver.close();
+        }
+        closed
+    }
+
+    /// Checks if all the channels have been closed.
+    pub fn is_closed(&self) -> bool{
+        self.receivers[0].is_closed()
+    }
+
+    /// Check if the activate channel is empty.
+    pub fn is_empty(&self) -> bool{
+        self.receivers[self.count.lane(N)].is_empty()
+    }
+
+    /// Check if the activate channel is full, unbounded channels are never full.
+    pub fn is_full(&self) -> bool{
+        self.receivers[self.count.lane(N)].is_full()
+    }
+
+    /// Returns the number of messages in the activate channel.
+    pub fn len(&self) -> usize{
+        self.receivers[self.count.lane(N)].len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        // These are only allowed be constructed with uniform capacity,
+        // so you can just get the capacity from any instance.
+        self.receivers[0].capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.receivers[0].sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        1
+    }
+
+    pub fn get_guard(&self) -> SwitchReceiverGuard<'_, T>{
+        SwitchReceiverGuard{
+            receiver: &self.receivers[self.count.lane(N)]
+        }
+    }
+}
+
+pub struct SwitchReceiverGuard<'a, T>{
+    receiver: &'a ReceiverLane<T>
+}
+
+impl<'a, T> Clone for SwitchReceiverGuard<'a, T>{
+    fn clone(&self) -> Self{
+        Self{
+            receiver: self.receiver
+        }
+    }
+}
+
+impl<'a, T> SwitchReceiverGuard<'a, T>{
+    /// Try to receive from the activate channel.
+    pub fn try_recv(&self) -> Result<T, TryRecvError>{
+        self.receiver.try_recv()
+    }
+
+    /// receive from the activate channel.
+    pub async fn recv(&'_ self) -> Result<T, RecvError>{
+        self.receiver.recv().await.ok_or(RecvError)
+    }
+
+    /// Checks if all the channels have been closed.
+    pub fn is_closed(&self) -> bool{
+        self.receiver.is_closed()
+    }
+
+    /// Check if the activate channel is empty.
+    pub fn is_empty(&self) -> bool{
+        self.receiver.is_empty()
+    }
+
+    /// Check if the activate channel is full, unbounded channels are never full.
+    pub fn is_full(&self) -> bool{
+        self.receiver.is_full()
+    }
+
+    /// Returns the number of messages in the activate channel.
+    pub fn len(&self) -> usize{
+        self.receiver.len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.receiver.capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.receiver.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        1
+    }
+}
+
+impl<'a, T> std::iter::IntoIterator for SwitchReceiverGuard<'a, T>{
+    type Item = T;
+    type IntoIter = SwitchReceiverGuardIterator<'a, T>;
+
+    fn into_iter(self) -> <Self as std::iter::IntoIterator>::IntoIter {
+        SwitchReceiverGuardIterator{
+            receiver: self.receiver
+        }
+    }
+}
+
+pub struct SwitchReceiverGuardIterator<'a, T>{
+    receiver: &'a ReceiverLane<T>,
+}
+
+impl<'a, T> std::iter::Iterator for SwitchReceiverGuardIterator<'a, T>{
+
+    type Item = T;
+    fn next(&mut self) -> std::option::Option<<Self as std::iter::Iterator>::Item> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchReceiver<T, N, PERMITTED>{
+    type Output = SwitchReceiverGuard<'a, T>;
+
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<SwitchReceiverGuard<'a, T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state: SwitchState| SwitchReceiverGuard{
+            receiver: &self.receivers[state.lane]
+        })
+    }
+
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<SwitchReceiverGuard<'a, T>, SwitchReceiverGuard<'a, T>>{
+        let guard = |state: SwitchState| SwitchReceiverGuard{
+            receiver: &self.receivers[state.lane]
+        };
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
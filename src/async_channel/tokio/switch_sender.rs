@@ -0,0 +1,159 @@
+use std::sync::{Arc, Weak};
+use crate::{PERMITTED, SwitchCounter, SwitchOp, SwitchOutcome, SwitchState, switch_lane, update_lane};
+use crate::{Switcher, err::send::{SendError, TrySendError}};
+use super::lane::{LaneSender, ReceiverLane};
+
+pub struct SwitchSender<T, const N: usize, const P: bool>{
+    pub(crate) count: Arc<SwitchCounter>,
+    pub(crate) senders: [LaneSender<T>; N],
+    // tokio senders can't close a channel themselves,
+    // so they close it through the receiving lanes instead.
+    pub(crate) receivers: Weak<[ReceiverLane<T>]>,
+}
+
+impl<T, const N: usize, const P: bool> SwitchSender<T, N, P>{
+    pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>>{
+        self.senders[self.count.lane(N)].try_send(msg)
+    }
+
+    pub async fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
+        self.senders[self.count.lane(N)].send(msg).await
+    }
+
+    pub fn close(&self) -> bool{
+        match self.receivers.upgrade(){
+            Some(receivers) => {
+                let mut closed = false;
+                for receiver in receivers.iter(){
+                    closed |= receiver.close();
+                }
+                closed
+            },
+            // The receiver has been dropped, so the channel is already closed.
+            None => false,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool{
+        self.senders[0].is_closed()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.senders[self.count.lane(N)].is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.receivers
+            .upgrade()
+            .map(|receivers| receivers[self.count.lane(N)].len())
+            .unwrap_or(0)
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.senders[0].capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.senders[0].sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.receivers.strong_count()
+    }
+}
+
+pub struct SwitchSenderGuard<'a, T>{
+    sender: &'a LaneSender<T>,
+    receivers: &'a Weak<[ReceiverLane<T>]>,
+    lane: usize,
+}
+
+impl<'a, T> Clone for SwitchSenderGuard<'a, T>{
+    fn clone(&self) -> Self{
+        Self{
+            sender: self.sender,
+            receivers: self.receivers,
+            lane: self.lane,
+        }
+    }
+}
+
+impl<'a, T> SwitchSenderGuard<'a, T>{
+    pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>>{
+        self.sender.try_send(msg)
+    }
+
+    pub async fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
+        self.sender.send(msg).await
+    }
+
+    pub fn is_closed(&self) -> bool{
+        self.sender.is_closed()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.sender.is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.receivers
+            .upgrade()
+            .map(|receivers| receivers[self.lane].len())
+            .unwrap_or(0)
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.sender.capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.sender.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.receivers.strong_count()
+    }
+}
+
+impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchSender<T, N, PERMITTED>{
+    type Output = SwitchSenderGuard<'a, T>;
+
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<SwitchSenderGuard<'a, T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state: SwitchState| SwitchSenderGuard{
+            sender: &self.senders[state.lane],
+            receivers: &self.receivers,
+            lane: state.lane,
+        })
+    }
+
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<SwitchSenderGuard<'a, T>, SwitchSenderGuard<'a, T>>{
+        let guard = |state: SwitchState| SwitchSenderGuard{
+            sender: &self.senders[state.lane],
+            receivers: &self.receivers,
+            lane: state.lane,
+        };
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
+
+impl<T, const N: usize, const P: bool> Clone for SwitchSender<T, N, P>{
+    fn clone(&self) -> Self{
+        Self{
+            count: self.count.clone(),
+            senders: self.senders.clone(),
+            receivers: self.receivers.clone(),
+        }
+    }
+}
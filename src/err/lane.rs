@@ -0,0 +1,42 @@
+/// Returned when addressing a lane that a switch channel doesn't have.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct OutOfRangeError{
+    /// The lane that was asked for.
+    pub lane: usize,
+    /// The number of lanes in the channel.
+    pub lanes: usize,
+}
+
+impl std::error::Error for OutOfRangeError {}
+
+impl std::fmt::Display for OutOfRangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "lane {} is out of range for a channel with {} lanes", self.lane, self.lanes)
+    }
+}
+
+/// Returned when a lane of a switch channel can't be retired.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RetireError{
+    /// The lane to retire, or the lane to redirect it to, doesn't exist.
+    OutOfRange(OutOfRangeError),
+    /// The lane would be redirected to itself, which is all that's left when it's the only lane.
+    SameLane(usize),
+}
+
+impl From<OutOfRangeError> for RetireError{
+    fn from(err: OutOfRangeError) -> Self{
+        Self::OutOfRange(err)
+    }
+}
+
+impl std::error::Error for RetireError {}
+
+impl std::fmt::Display for RetireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            RetireError::OutOfRange(err) => err.fmt(f),
+            RetireError::SameLane(lane) => write!(f, "lane {} can't be redirected to itself", lane),
+        }
+    }
+}
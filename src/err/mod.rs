@@ -1,2 +1,3 @@
+pub mod lane;
 pub mod recv;
 pub mod send;
\ No newline at end of file
@@ -31,4 +31,45 @@ impl std::fmt::Display for RecvError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "receiving from an empty and closed channel")
     }
-}
\ No newline at end of file
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RecvTimeoutError{
+    Timeout,
+    Closed,
+}
+
+impl RecvTimeoutError {
+    /// Returns `true` if nothing was received before the timeout.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            RecvTimeoutError::Timeout => true,
+            RecvTimeoutError::Closed => false,
+        }
+    }
+
+    /// Returns `true` if the channel is empty and closed.
+    pub fn is_closed(&self) -> bool {
+        match self {
+            RecvTimeoutError::Timeout => false,
+            RecvTimeoutError::Closed => true,
+        }
+    }
+}
+
+impl From<RecvError> for RecvTimeoutError {
+    fn from(_: RecvError) -> Self {
+        RecvTimeoutError::Closed
+    }
+}
+
+impl std::error::Error for RecvTimeoutError {}
+
+impl std::fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            RecvTimeoutError::Timeout => write!(f, "timed out waiting on receive operation"),
+            RecvTimeoutError::Closed => write!(f, "receiving from an empty and closed channel"),
+        }
+    }
+}
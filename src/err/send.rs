@@ -35,4 +35,46 @@ impl<T> std::fmt::Display for SendError<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "sending into a closed channel")
     }
-}
\ No newline at end of file
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum SendTimeoutError<T>{
+    Timeout(T),
+    Closed(T),
+}
+
+impl<T> SendTimeoutError<T> {
+    /// Returns the message that couldn't be sent.
+    pub fn into_inner(self) -> T {
+        match self {
+            SendTimeoutError::Timeout(msg) => msg,
+            SendTimeoutError::Closed(msg) => msg,
+        }
+    }
+}
+
+impl<T> From<SendError<T>> for SendTimeoutError<T> {
+    fn from(err: SendError<T>) -> Self {
+        SendTimeoutError::Closed(err.0)
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendTimeoutError<T> {}
+
+impl<T> fmt::Debug for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SendTimeoutError::Timeout(..) => write!(f, "Timeout(..)"),
+            SendTimeoutError::Closed(..) => write!(f, "Closed(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SendTimeoutError::Timeout(..) => write!(f, "timed out waiting on send operation"),
+            SendTimeoutError::Closed(..) => write!(f, "sending into a closed channel"),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::sync::{Arc, LockResult, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard, atomic::{AtomicBool, AtomicUsize, Ordering}};
+use event_listener::{Event, EventListener};
+use crate::{SwitchCounter, update_lane};
+use crate::err::lane::{OutOfRangeError, RetireError};
+
+/// A lane of a backend that can be kept in a `LaneTable`.
+pub(crate) trait TableLane{
+    /// A bounded lane with room for `capacity` messages, or an unbounded one.
+    fn new(capacity: Option<usize>) -> Self;
+
+    /// Returns `true` if this call closed the lane.
+    fn close(&self) -> bool;
+}
+
+/// The number of sender and receiver handles of a switch channel, for lanes that can't count them themselves.
+///
+/// The channel is closed once either side has no handles left.
+pub(crate) struct Handles{
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+}
+
+impl Handles{
+    pub(crate) fn new() -> Self{
+        Self{
+            senders: AtomicUsize::new(1),
+            receivers: AtomicUsize::new(1),
+        }
+    }
+
+    pub(crate) fn add_sender(&self){
+        self.senders.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if this was the last sender.
+    pub(crate) fn remove_sender(&self) -> bool{
+        self.senders.fetch_sub(1, Ordering::SeqCst) == 1
+    }
+
+    pub(crate) fn add_receiver(&self){
+        self.receivers.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if this was the last receiver.
+    pub(crate) fn remove_receiver(&self) -> bool{
+        self.receivers.fetch_sub(1, Ordering::SeqCst) == 1
+    }
+
+    pub(crate) fn sender_count(&self) -> usize{
+        self.senders.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn receiver_count(&self) -> usize{
+        self.receivers.load(Ordering::SeqCst)
+    }
+}
+
+// Enough chunks for every slot a `usize` can count.
+const CHUNKS: usize = usize::BITS as usize;
+
+/// The lanes of a switch channel that can have lanes added and retired while it's in use.
+///
+/// Lanes are addressed by their position among the live lanes, which is what the switch counter holds.
+/// Every lane keeps its slot until the table is dropped, in chunks that double in size,
+/// so guards can borrow a lane while others come and go. Retired lanes are left closed.
+pub(crate) struct LaneTable<L>{
+    chunks: [OnceLock<Box<[OnceLock<L>]>>; CHUNKS],
+    live: LiveSlots,
+    // The number of live lanes, which can be shared without the lanes themselves.
+    live_count: Arc<AtomicUsize>,
+    capacity: Option<usize>,
+    closed: AtomicBool,
+    // Notified whenever a lane is added or retired.
+    changed: Event,
+}
+
+pub(crate) struct Live{
+    // The slot of each live lane, by position.
+    slots: Vec<usize>,
+    // The number of slots that have ever been filled.
+    used: usize,
+}
+
+/// Which slot each live lane of a `LaneTable` is in, so a guard can look up where its lane is now.
+pub(crate) struct LiveSlots(RwLock<Live>);
+
+impl LiveSlots{
+    fn read(&self) -> LockResult<RwLockReadGuard<'_, Live>>{
+        self.0.read()
+    }
+
+    fn write(&self) -> LockResult<RwLockWriteGuard<'_, Live>>{
+        self.0.write()
+    }
+
+    /// Whether the lane in `slot` is the active one according to `count`, which it never is once it's been retired.
+    pub(crate) fn is_active(&self, slot: usize, count: &SwitchCounter) -> bool{
+        // Lanes are only retired or added with the lock held, so the position and the count agree.
+        let live = self.read().unwrap();
+        live.slots.iter().position(|&live| live == slot).is_some_and(|lane| count.lane(live.slots.len()) == lane)
+    }
+}
+
+/// The live lanes of a `LaneTable`, which can't be added to or retired while this is held.
+pub(crate) struct LiveLanes<'a, L>{
+    table: &'a LaneTable<L>,
+    live: RwLockReadGuard<'a, Live>,
+}
+
+impl<'a, L> LiveLanes<'a, L>{
+    pub(crate) fn len(&self) -> usize{
+        self.live.slots.len()
+    }
+
+    pub(crate) fn slot(&self, lane: usize) -> usize{
+        self.live.slots[lane]
+    }
+
+    pub(crate) fn get(&self, lane: usize) -> &'a L{
+        self.table.slot(self.live.slots[lane])
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &'a L> + '_{
+        let table = self.table;
+        self.live.slots.iter().map(move |&slot| table.slot(slot))
+    }
+}
+
+impl<L> LaneTable<L>{
+    /// The chunk a slot is in, and where in it. Chunk `k` holds the `2^k` slots from `2^k - 1` on.
+    fn locate(slot: usize) -> (usize, usize){
+        let chunk = (usize::BITS - 1 - (slot + 1).leading_zeros()) as usize;
+        (chunk, slot + 1 - (1 << chunk))
+    }
+
+    /// The lane in `slot`, whether it's live or retired.
+    pub(crate) fn slot(&self, slot: usize) -> &L{
+        let (chunk, offset) = Self::locate(slot);
+        self.chunks[chunk].get().and_then(|lanes| lanes[offset].get()).expect("slots are filled before they're handed out")
+    }
+
+    pub(crate) fn lanes(&self) -> LiveLanes<'_, L>{
+        LiveLanes{
+            table: self,
+            live: self.live.read().unwrap(),
+        }
+    }
+
+    /// The active lane according to `count`.
+    pub(crate) fn active(&self, count: &SwitchCounter) -> &L{
+        let lanes = self.lanes();
+        lanes.get(count.lane(lanes.len()))
+    }
+
+    pub(crate) fn is_closed(&self) -> bool{
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// The capacity every lane is created with, `None` if they're unbounded.
+    pub(crate) fn capacity(&self) -> Option<usize>{
+        self.capacity
+    }
+
+    pub(crate) fn live_slots(&self) -> &LiveSlots{
+        &self.live
+    }
+
+    pub(crate) fn listen_changed(&self) -> EventListener{
+        self.changed.listen()
+    }
+
+    /// The number of live lanes, for a task that mustn't hold on to the lanes.
+    #[cfg(feature = "async_std")]
+    pub(crate) fn live_count(&self) -> Arc<AtomicUsize>{
+        self.live_count.clone()
+    }
+}
+
+impl<L: TableLane> LaneTable<L>{
+    /// Panics if `lanes` is 0.
+    pub(crate) fn new(lanes: usize, capacity: Option<usize>) -> Self{
+        assert!(lanes > 0, "a switch channel needs at least one lane");
+        let table = Self{
+            chunks: std::array::from_fn(|_| OnceLock::new()),
+            live: LiveSlots(RwLock::new(Live{ slots: Vec::with_capacity(lanes), used: 0 })),
+            live_count: Arc::new(AtomicUsize::new(0)),
+            capacity,
+            closed: AtomicBool::new(false),
+            changed: Event::new(),
+        };
+        for _ in 0..lanes{
+            table.add();
+        }
+        table
+    }
+
+    /// Adds a lane after the others, returning its position.
+    pub(crate) fn add(&self) -> usize{
+        let mut live = self.live.write().unwrap();
+        let slot = live.used;
+        let (chunk, offset) = Self::locate(slot);
+        let lanes = self.chunks[chunk].get_or_init(|| (0..1 << chunk).map(|_| OnceLock::new()).collect());
+        let lane = lanes[offset].get_or_init(|| L::new(self.capacity));
+        // `close` only sees the slots used before it, so a lane added while closing has to close itself.
+        if self.is_closed(){
+            lane.close();
+        }
+        live.used += 1;
+        live.slots.push(slot);
+        self.live_count.store(live.slots.len(), Ordering::SeqCst);
+        let position = live.slots.len() - 1;
+        drop(live);
+        self.changed.notify(usize::MAX);
+        position
+    }
+
+    /// Retires and closes the lane at position `lane`, redirecting `count` to the lane at position `to` if it was active.
+    ///
+    /// The lanes after it move down a position. Returns the retired lane and the lane at `to`,
+    /// so whatever is left in the retired lane can be moved over.
+    pub(crate) fn retire(&self, count: &SwitchCounter, lane: usize, to: usize) -> Result<(&L, &L), RetireError>{
+        let mut live = self.live.write().unwrap();
+        let n = live.slots.len();
+        if lane >= n{
+            return Err(OutOfRangeError{ lane, lanes: n }.into());
+        }
+        if to >= n{
+            return Err(OutOfRangeError{ lane: to, lanes: n }.into());
+        }
+        if to == lane{
+            return Err(RetireError::SameLane(lane));
+        }
+        let moved = |position: usize| if position > lane { position - 1 } else { position };
+        let _ = update_lane(count, n, |active| Some(moved(if active == lane { to } else { active })));
+        let retired = self.slot(live.slots.remove(lane));
+        self.live_count.store(live.slots.len(), Ordering::SeqCst);
+        let target = self.slot(live.slots[moved(to)]);
+        drop(live);
+        self.changed.notify(usize::MAX);
+        // Anything still holding the retired lane finds it closed from here on.
+        retired.close();
+        Ok((retired, target))
+    }
+
+    /// Closes every lane, returning `true` if this call closed any of them.
+    pub(crate) fn close(&self) -> bool{
+        self.closed.store(true, Ordering::SeqCst);
+        let live = self.live.read().unwrap();
+        let mut closed = false;
+        for slot in 0..live.used{
+            closed |= self.slot(slot).close();
+        }
+        closed
+    }
+}
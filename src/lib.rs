@@ -5,18 +5,283 @@
 pub mod err;
 pub mod async_channel;
 pub mod sync_channel;
+mod switch_group;
+mod rotation;
+mod lane_table;
+mod ready;
+
+pub use switch_group::SwitchGroup;
+pub use rotation::{Rotation, StarvationGuard};
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use event_listener::{Event, EventListener};
+use lane_table::LiveSlots;
 
 pub trait Switcher<'a, T>{
     type Output;
 
-    fn switch_add(&'a self, val: usize) -> Self::Output;
-    fn switch_and(&'a self, val: usize) -> Self::Output;
-    fn switch_max(&'a self, val: usize) -> Self::Output;
-    fn switch_min(&'a self, val: usize) -> Self::Output;
-    fn switch_nand(&'a self, val: usize) -> Self::Output;
-    fn switch_or(&'a self, val: usize) -> Self::Output;
-    fn switch_sub(&'a self, val: usize) -> Self::Output;
-    fn switch_xor(&'a self, val: usize) -> Self::Output;
+    /// Atomically applies `op` to the switch counter, returning guards for both
+    /// the lane that was active before the switch and the lane that is active after it.
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<Self::Output>;
+
+    /// Switches to the lane returned by `f`, like `AtomicUsize::fetch_update`.
+    ///
+    /// `f` is given the active lane and may be called several times if another switcher
+    /// changes the counter in the meantime. The lane it returns is wrapped modulo the number of lanes.
+    /// Returns `Ok` with a guard for the lane that was switched away from,
+    /// or `Err` with a guard for the observed lane if `f` returned `None`.
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<Self::Output, Self::Output>;
+
+    /// Switches from lane `current` to lane `new`, only if `current` is still the active lane.
+    ///
+    /// Returns `Ok` with a guard for `current` if the switch happened,
+    /// or `Err` with a guard for the lane that was active instead.
+    fn switch_compare_exchange(&'a self, current: usize, new: usize) -> Result<Self::Output, Self::Output>{
+        self.switch_update(|lane| if lane == current { Some(new) } else { None })
+    }
+
+    fn switch_add(&'a self, val: usize) -> Self::Output{
+        self.switch_outcome(SwitchOp::Add(val)).previous
+    }
+
+    fn switch_and(&'a self, val: usize) -> Self::Output{
+        self.switch_outcome(SwitchOp::And(val)).previous
+    }
+
+    fn switch_max(&'a self, val: usize) -> Self::Output{
+        self.switch_outcome(SwitchOp::Max(val)).previous
+    }
+
+    fn switch_min(&'a self, val: usize) -> Self::Output{
+        self.switch_outcome(SwitchOp::Min(val)).previous
+    }
+
+    fn switch_nand(&'a self, val: usize) -> Self::Output{
+        self.switch_outcome(SwitchOp::Nand(val)).previous
+    }
+
+    fn switch_or(&'a self, val: usize) -> Self::Output{
+        self.switch_outcome(SwitchOp::Or(val)).previous
+    }
+
+    fn switch_sub(&'a self, val: usize) -> Self::Output{
+        self.switch_outcome(SwitchOp::Sub(val)).previous
+    }
+
+    fn switch_xor(&'a self, val: usize) -> Self::Output{
+        self.switch_outcome(SwitchOp::Xor(val)).previous
+    }
+}
+
+/// Both sides of a single switch, taken from the same counter transition.
+#[derive(Clone, Debug)]
+pub struct SwitchOutcome<G>{
+    /// A guard for the lane that was active before the switch.
+    pub previous: G,
+    /// The index of the lane that was active before the switch.
+    pub previous_lane: usize,
+    /// The epoch the channel was in before the switch.
+    pub previous_epoch: usize,
+    /// A guard for the lane that is active after the switch.
+    pub current: G,
+    /// The index of the lane that is active after the switch.
+    pub current_lane: usize,
+    /// The epoch the channel is in after the switch, only different from `previous_epoch` if the lane changed.
+    pub current_epoch: usize,
+}
+
+impl<G> SwitchOutcome<G>{
+    pub(crate) fn from_states((previous, current): (SwitchState, SwitchState), guard: impl Fn(SwitchState) -> G) -> Self{
+        SwitchOutcome{
+            previous: guard(previous),
+            previous_lane: previous.lane,
+            previous_epoch: previous.epoch,
+            current: guard(current),
+            current_lane: current.lane,
+            current_epoch: current.epoch,
+        }
+    }
+
+    /// Returns `true` if the switch moved the counter to a different lane.
+    pub fn switched(&self) -> bool{
+        self.previous_lane != self.current_lane
+    }
+}
+
+/// The operations a `Switcher` can apply to its switch counter.
+///
+/// `Add` and `Sub` wrap like arithmetic modulo the number of lanes, so subtracting
+/// from lane 0 lands on the last lane. The bitwise and min/max operations are applied
+/// to the lane index as is, and the result is then reduced modulo the number of lanes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwitchOp{
+    Add(usize),
+    And(usize),
+    Max(usize),
+    Min(usize),
+    Nand(usize),
+    Or(usize),
+    Sub(usize),
+    Xor(usize),
+}
+
+impl SwitchOp{
+    /// Applies the operation to `lane`, wrapping the result back into `0..n`.
+    pub(crate) fn apply(self, lane: usize, n: usize) -> usize{
+        match self{
+            SwitchOp::Add(val) => (lane + val % n) % n,
+            SwitchOp::And(val) => (lane & val) % n,
+            SwitchOp::Max(val) => lane.max(val) % n,
+            SwitchOp::Min(val) => lane.min(val) % n,
+            SwitchOp::Nand(val) => !(lane & val) % n,
+            SwitchOp::Or(val) => (lane | val) % n,
+            SwitchOp::Sub(val) => (lane + n - val % n) % n,
+            SwitchOp::Xor(val) => (lane ^ val) % n,
+        }
+    }
+}
+
+// The switch counter packs the lane into the low half of its bits, and the epoch into the high half,
+// so both change together in a single atomic operation.
+const LANE_BITS: u32 = usize::BITS / 2;
+const LANE_MASK: usize = usize::MAX >> LANE_BITS;
+
+/// The lane a switch counter is on, and the epoch it's in.
+///
+/// The epoch starts at 0 and moves on every time the counter switches to a different lane,
+/// wrapping around after `2^(usize::BITS / 2)` switches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SwitchState{
+    pub(crate) lane: usize,
+    pub(crate) epoch: usize,
+}
+
+impl SwitchState{
+    fn unpack(state: usize, n: usize) -> Self{
+        Self{
+            lane: (state & LANE_MASK) % n,
+            epoch: state >> LANE_BITS,
+        }
+    }
+
+    fn pack(self) -> usize{
+        self.epoch << LANE_BITS | self.lane
+    }
+
+    /// Moves to `lane`, starting a new epoch if that's a different lane.
+    fn switch_to(self, lane: usize) -> Self{
+        if lane == self.lane{
+            self
+        }else{
+            Self{
+                lane,
+                epoch: self.epoch.wrapping_add(1) & LANE_MASK,
+            }
+        }
+    }
+}
+
+/// The switch counter shared by the handles of a switch channel.
+///
+/// Anyone listening is woken up whenever `switch_lane` or `update_lane` move it to a different lane.
+#[derive(Debug, Default)]
+pub(crate) struct SwitchCounter{
+    state: AtomicUsize,
+    switched: Event,
+}
+
+impl SwitchCounter{
+    pub(crate) fn new() -> Self{
+        Self::default()
+    }
+
+    /// The lane and epoch of a channel with `n` lanes.
+    pub(crate) fn state(&self, n: usize) -> SwitchState{
+        SwitchState::unpack(self.state.load(Ordering::SeqCst), n)
+    }
+
+    /// The active lane of a channel with `n` lanes.
+    pub(crate) fn lane(&self, n: usize) -> usize{
+        self.state(n).lane
+    }
+
+    pub(crate) fn epoch(&self) -> usize{
+        self.state.load(Ordering::SeqCst) >> LANE_BITS
+    }
+
+    /// Start listening for the next switch to a different lane.
+    pub(crate) fn listen(&self) -> EventListener{
+        self.switched.listen()
+    }
+}
+
+/// The lane a guard was taken for, along with the switch counter it was taken from.
+#[derive(Clone, Copy)]
+pub(crate) struct Position<'a>{
+    pub(crate) count: &'a SwitchCounter,
+    pub(crate) state: SwitchState,
+    pub(crate) lanes: Lanes<'a>,
+}
+
+/// Where a guard's lane is among the lanes of its channel.
+#[derive(Clone, Copy)]
+pub(crate) enum Lanes<'a>{
+    /// One of a fixed number of lanes, which never moves.
+    Fixed(usize),
+    /// The lane in a slot of a `LaneTable`, which moves down as the lanes before it are retired.
+    Table(&'a LiveSlots, usize),
+}
+
+impl<'a> Position<'a>{
+    /// Whether the guard's lane is still the active one.
+    pub(crate) fn is_active(&self) -> bool{
+        match self.lanes{
+            Lanes::Fixed(n) => self.count.lane(n) == self.state.lane,
+            Lanes::Table(slots, slot) => slots.is_active(slot, self.count),
+        }
+    }
+
+    /// Whether the channel is still in the epoch the guard was taken in.
+    pub(crate) fn is_current(&self) -> bool{
+        self.count.epoch() == self.state.epoch
+    }
+}
+
+/// Atomically applies `op` to the switch counter of a channel with `n` lanes,
+/// returning the state of the counter before and after the switch.
+pub(crate) fn switch_lane(count: &SwitchCounter, n: usize, op: SwitchOp) -> (SwitchState, SwitchState){
+    let switch = |state: usize| {
+        let state = SwitchState::unpack(state, n);
+        state.switch_to(op.apply(state.lane, n))
+    };
+    // The update never returns `None`, so both arms hold the previous value.
+    let previous = match count.state.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |state| Some(switch(state).pack())){
+        Ok(state) | Err(state) => state,
+    };
+    let (previous, current) = (SwitchState::unpack(previous, n), switch(previous));
+    if previous != current{
+        count.switched.notify(usize::MAX);
+    }
+    (previous, current)
+}
+
+/// Atomically switches the counter of a channel with `n` lanes to the lane returned by `f`,
+/// returning the previous state, or the observed state if `f` returned `None`.
+pub(crate) fn update_lane(count: &SwitchCounter, n: usize, mut f: impl FnMut(usize) -> Option<usize>) -> Result<SwitchState, SwitchState>{
+    let mut switched = false;
+    count.state.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |state| {
+        let state = SwitchState::unpack(state, n);
+        let current = state.switch_to(f(state.lane)? % n);
+        switched = current != state;
+        Some(current.pack())
+    })
+    .map(|previous| {
+        if switched{
+            count.switched.notify(usize::MAX);
+        }
+        SwitchState::unpack(previous, n)
+    })
+    .map_err(|state| SwitchState::unpack(state, n))
 }
 
 pub const PERMITTED: bool = true;
@@ -0,0 +1,20 @@
+use event_listener::EventListener;
+
+/// Something a `Select` of either backend can wait on.
+pub(crate) trait Ready{
+    /// Whether a receive wouldn't have to wait.
+    fn is_ready(&self) -> bool;
+
+    /// Start listening for anything that could make this ready.
+    fn listen(&self, listeners: &mut Vec<EventListener>);
+}
+
+impl<T: ?Sized + Ready> Ready for &T{
+    fn is_ready(&self) -> bool{
+        (**self).is_ready()
+    }
+
+    fn listen(&self, listeners: &mut Vec<EventListener>){
+        (**self).listen(listeners)
+    }
+}
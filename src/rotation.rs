@@ -0,0 +1,221 @@
+use std::any::Any;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use crate::{SwitchCounter, SwitchState, update_lane};
+use crate::err::recv::TryRecvError;
+
+/// How a switch receiver switches lanes by itself while receiving.
+///
+/// Rotating always moves on to the next lane, wrapping around after the last one,
+/// so every lane with messages is served in turn. A limit of 0 messages counts as 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rotation{
+    /// Move on to the next lane whenever the active lane is empty.
+    OnEmpty,
+    /// Move on after receiving this many messages from the active lane, or sooner if it's empty.
+    After(usize),
+    /// Deficit round robin where every message costs the same: each turn, a lane is served
+    /// up to its weight in messages, or until it's empty. The weights are given per lane.
+    ///
+    /// While every lane has messages, each gets its weight's share of them,
+    /// e.g. `Weighted(vec![7, 3])` serves 70% from lane 0 and 30% from lane 1.
+    /// Every weight has to be at least 1. A lane added to a dynamic channel after the rotation was set
+    /// has no weight in the list, so it gets a weight of 1.
+    Weighted(Vec<usize>),
+    /// Always serve the first lane with messages, lane 0 before lane 1 and so on.
+    ///
+    /// The receiver picks its lane afresh on every receive, so this never switches the channel,
+    /// and the senders of a channel that shares its switch with the receiver stay on their lane.
+    ///
+    /// When the guard trips, the next receive gives the lanes after lane 0 a turn instead,
+    /// starting where the last such turn left off, so no lane can be starved for good.
+    Priority(StarvationGuard),
+    /// Move on after a lane has been active for this long, or sooner if it's empty.
+    ///
+    /// The std backend measures the slice with `Instant` from when the receiver first sees the lane active,
+    /// and checks it whenever it receives. The async_std backend rotates on its async timer instead,
+    /// with a task that runs for as long as a receiver with the rotation is around.
+    TimeSlice(Duration),
+}
+
+/// When a `Rotation::Priority` receiver has to give the lower priority lanes a turn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StarvationGuard{
+    /// Never, lower priority lanes are only served while every lane before them is empty.
+    Off,
+    /// After this many messages since the last turn.
+    Messages(usize),
+    /// Once this much time has passed since the last turn.
+    Time(Duration),
+}
+
+/// Keeps track of what a rotating receiver handle has received since the channel last switched.
+pub(crate) struct Rotator{
+    rotation: Rotation,
+    received: Mutex<Received>,
+    // Keeps the backend's time slice timer running, if it has one, in which case receives don't check the slice.
+    timer: Option<Arc<dyn Any + Send + Sync>>,
+}
+
+struct Received{
+    // The epoch the messages were counted in, and when it was first seen.
+    epoch: usize,
+    started: Instant,
+    count: usize,
+    // When the lower priority lanes last had a turn, and which lane that turn starts at next.
+    since: Instant,
+    turn: usize,
+}
+
+impl Received{
+    fn new() -> Self{
+        Self{
+            epoch: 0,
+            started: Instant::now(),
+            count: 0,
+            since: Instant::now(),
+            turn: 1,
+        }
+    }
+
+    /// Starts counting from scratch if the channel has moved on to a new epoch.
+    fn observe(&mut self, state: SwitchState){
+        if self.epoch != state.epoch{
+            self.epoch = state.epoch;
+            self.started = Instant::now();
+            self.count = 0;
+        }
+    }
+}
+
+impl Rotator{
+    /// Panics if the rotation is `Weighted` and doesn't have a weight of at least 1 for each of the `n` lanes.
+    pub(crate) fn new(rotation: Rotation, n: usize) -> Self{
+        if let Rotation::Weighted(weights) = &rotation{
+            assert_eq!(n, weights.len(), "`Rotation::Weighted` needs a weight for every lane");
+            assert!(!weights.contains(&0), "`Rotation::Weighted` can't have a weight of 0");
+        }
+        Self{
+            rotation,
+            received: Mutex::new(Received::new()),
+            timer: None,
+        }
+    }
+
+    /// Leaves the time slices to a timer, which keeps running while this or a clone holds on to `timer`.
+    #[cfg(feature = "async_std")]
+    pub(crate) fn with_timer(mut self, timer: Arc<dyn Any + Send + Sync>) -> Self{
+        self.timer = Some(timer);
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Received>{
+        self.received.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Try to receive from the active lane, rotating past empty lanes until each of the `n` lanes has been tried once.
+    ///
+    /// Only returns `Closed` once every lane is closed.
+    pub(crate) fn try_recv<T>(&self, count: &SwitchCounter, n: usize, mut try_recv: impl FnMut(usize) -> Result<T, TryRecvError>) -> Result<T, TryRecvError>{
+        if let Rotation::Priority(guard) = self.rotation{
+            return self.try_recv_priority(n, guard, try_recv);
+        }
+        if let (Rotation::TimeSlice(slice), None) = (&self.rotation, &self.timer){
+            let slice = *slice;
+            let state = count.state(n);
+            let mut received = self.lock();
+            received.observe(state);
+            if received.started.elapsed() >= slice{
+                rotate(count, n, state);
+            }
+        }
+        let mut closed = 0;
+        for _ in 0..n{
+            let state = count.state(n);
+            match try_recv(state.lane){
+                Ok(msg) => {
+                    self.received(count, n, state);
+                    return Ok(msg);
+                },
+                Err(TryRecvError::Closed) => closed += 1,
+                Err(TryRecvError::Empty) => {},
+            }
+            rotate(count, n, state);
+        }
+        if closed == n{
+            Err(TryRecvError::Closed)
+        }else{
+            Err(TryRecvError::Empty)
+        }
+    }
+
+    fn received(&self, count: &SwitchCounter, n: usize, state: SwitchState){
+        let limit = match self.rotation{
+            Rotation::OnEmpty | Rotation::Priority(_) | Rotation::TimeSlice(_) => return,
+            Rotation::After(limit) => limit,
+            // A lane added to a dynamic channel after the rotation was set doesn't have a weight of its own.
+            Rotation::Weighted(ref weights) => weights.get(state.lane).copied().unwrap_or(1),
+        };
+        let mut received = self.lock();
+        received.observe(state);
+        received.count += 1;
+        if received.count >= limit{
+            rotate(count, n, state);
+        }
+    }
+
+    fn try_recv_priority<T>(&self, n: usize, guard: StarvationGuard, mut try_recv: impl FnMut(usize) -> Result<T, TryRecvError>) -> Result<T, TryRecvError>{
+        let mut received = self.lock();
+        let starving = n > 1 && match guard{
+            StarvationGuard::Off => false,
+            StarvationGuard::Messages(limit) => received.count >= limit,
+            StarvationGuard::Time(limit) => received.since.elapsed() >= limit,
+        };
+        let first = if starving { received.turn } else { 0 };
+        let mut closed = 0;
+        for offset in 0..n{
+            let lane = (first + offset) % n;
+            match try_recv(lane){
+                Ok(msg) => {
+                    // Serving any lower priority lane counts as their turn, even if it was only because
+                    // every lane before it was empty, so only messages from lane 0 add up to the next one.
+                    if lane != 0 || starving{
+                        received.count = 0;
+                        received.since = Instant::now();
+                        // The next turn starts after this lane, unless every lower priority lane was empty.
+                        if lane != 0{
+                            received.turn = lane % (n - 1) + 1;
+                        }
+                    }else{
+                        received.count += 1;
+                    }
+                    return Ok(msg);
+                },
+                Err(TryRecvError::Closed) => closed += 1,
+                Err(TryRecvError::Empty) => {},
+            }
+        }
+        if closed == n{
+            Err(TryRecvError::Closed)
+        }else{
+            Err(TryRecvError::Empty)
+        }
+    }
+}
+
+impl Clone for Rotator{
+    /// A clone starts counting from scratch, each handle only counts its own messages.
+    /// It shares the timer though, a channel only needs the one.
+    fn clone(&self) -> Self{
+        Self{
+            rotation: self.rotation.clone(),
+            received: Mutex::new(Received::new()),
+            timer: self.timer.clone(),
+        }
+    }
+}
+
+/// Moves on from `state` to the next lane, unless another handle has already switched away from it.
+pub(crate) fn rotate(count: &SwitchCounter, n: usize, state: SwitchState){
+    let _ = update_lane(count, n, |lane| if lane == state.lane { Some(lane + 1) } else { None });
+}
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+use crate::{SwitchCounter, SwitchOp, switch_lane};
+
+/// A switch counter that can be shared by several switch channels.
+///
+/// Channels created from the same group always sit on the same lane,
+/// so switching any one of them, or the group itself, switches all of them at once.
+/// The channels can carry different message types, but must all have `N` lanes.
+#[derive(Clone, Debug, Default)]
+pub struct SwitchGroup<const N: usize>{
+    pub(crate) count: Arc<SwitchCounter>,
+}
+
+impl<const N: usize> SwitchGroup<N>{
+    pub fn new() -> Self{
+        Self{
+            count: Arc::new(SwitchCounter::new()),
+        }
+    }
+
+    /// Returns the lane every channel in the group is on.
+    pub fn lane(&self) -> usize{
+        self.count.lane(N)
+    }
+
+    /// Returns the epoch the group is in, which moves on every time the group switches to a different lane.
+    pub fn epoch(&self) -> usize{
+        self.count.epoch()
+    }
+
+    /// Atomically applies `op` to every channel in the group,
+    /// returning the lanes that were active before and after the switch.
+    pub fn switch(&self, op: SwitchOp) -> (usize, usize){
+        let (previous, current) = switch_lane(&self.count, N, op);
+        (previous.lane, current.lane)
+    }
+}
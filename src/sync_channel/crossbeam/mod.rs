@@ -0,0 +1,174 @@
+mod switch_receiver;
+mod switch_sender;
+mod diswitchchannel;
+
+pub use switch_receiver::{SwitchReceiver, SwitchReceiverGuard, SwitchReceiverGuardIterator, SwitchReceiverGuardTryIterator};
+pub use switch_sender::{SwitchSender, SwitchSenderGuard};
+pub use diswitchchannel::{dibounded, diunbounded, DiSwitchSender, DiSwitchReceiver};
+
+use std::convert::TryInto;
+use crate::SwitchCounter;
+use std::sync::Arc;
+use core::iter::repeat_with;
+use crate::err::send::{SendError, TrySendError};
+use crate::err::recv::{RecvError, TryRecvError};
+
+pub fn bounded<T, const N: usize, const S: bool, const P: bool>(cap: usize) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    use crossbeam_channel::{bounded, Sender, Receiver};
+
+    let (senders, receivers): (Vec<Sender<T>>, Vec<Receiver<T>>) = repeat_with(|| bounded(cap)).take(N).unzip();
+
+    let switch = Arc::new(SwitchCounter::new());
+    (
+        SwitchSender{
+            count: switch.clone(),
+            senders: senders.try_into().unwrap()
+        },
+        SwitchReceiver{
+            count: switch,
+            receivers: receivers.try_into().unwrap()
+        }
+    )
+}
+
+pub fn unbounded<T, const N: usize, const S: bool, const P: bool>() -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    use crossbeam_channel::{unbounded, Sender, Receiver};
+
+    let (senders, receivers): (Vec<Sender<T>>, Vec<Receiver<T>>) = repeat_with(unbounded).take(N).unzip();
+
+    let switch = Arc::new(SwitchCounter::new());
+    (
+        SwitchSender{
+            count: switch.clone(),
+            senders: senders.try_into().unwrap()
+        },
+        SwitchReceiver{
+            count: switch,
+            receivers: receivers.try_into().unwrap()
+        }
+    )
+}
+
+impl<T> From<crossbeam_channel::SendError<T>> for SendError<T>{
+    fn from(err: crossbeam_channel::SendError<T>) -> Self {
+        Self(err.0)
+    }
+}
+
+impl<T> From<crossbeam_channel::TrySendError<T>> for TrySendError<T>{
+    fn from(err: crossbeam_channel::TrySendError<T>) -> Self {
+        match err{
+            crossbeam_channel::TrySendError::Full(t) => Self::Full(t),
+            crossbeam_channel::TrySendError::Disconnected(t) => Self::Closed(t),
+        }
+    }
+}
+
+impl From<crossbeam_channel::TryRecvError> for TryRecvError{
+    fn from(err: crossbeam_channel::TryRecvError) -> Self {
+        match err{
+            crossbeam_channel::TryRecvError::Empty => Self::Empty,
+            crossbeam_channel::TryRecvError::Disconnected => Self::Closed,
+        }
+    }
+}
+
+impl From<crossbeam_channel::RecvError> for RecvError{
+    fn from(_: crossbeam_channel::RecvError) -> Self {
+        Self
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use crate::*;
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn constructors(){
+        let (_sender, _receiver) = unbounded::<u32, 10, false, false>();
+        let (_sender, _receiver) = unbounded::<u32, 10, false, true>();
+        let (_sender, _receiver) = unbounded::<u32, 10, true, false>();
+        let (_sender, _receiver) = unbounded::<u32, 10, true, true>();
+        let (_sender, _receiver) = bounded::<u32, 10, false, false>(10);
+        let (_sender, _receiver) = bounded::<u32, 10, false, true>(10);
+        let (_sender, _receiver) = bounded::<u32, 10, true, false>(10);
+        let (_sender, _receiver) = bounded::<u32, 10, true, true>(10);
+    }
+
+    #[test]
+    fn unbounded_send_receive() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 1, false, false>();
+        sender.send(10)?;
+        assert_eq!(10, receiver.recv()?);
+        Ok(())
+    }
+
+    #[test]
+    fn bounded_try_send_full(){
+        let (sender, receiver) = bounded::<u32, 2, false, false>(1);
+        assert!(sender.try_send(10).is_ok());
+        assert!(sender.is_full());
+        assert_eq!(Some(1), receiver.capacity());
+        assert!(matches!(sender.try_send(20), Err(err::send::TrySendError::Full(20))));
+    }
+
+    #[test]
+    fn unbounded_switch_send_receive() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 2, true, false>();
+        sender.switch_add(1).send(10)?;
+        assert_eq!(err::recv::TryRecvError::Empty, receiver.try_recv().err().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn unbounded_send_switch_receive() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 2, false, true>();
+        sender.send(10)?;
+        assert_eq!(10, receiver.switch_add(1).try_recv().ok().unwrap());
+        assert_eq!(err::recv::TryRecvError::Empty, receiver.try_recv().err().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn unbounded_switch_send_switch_receive() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 2, true, true>();
+        // This switches both the sender and receiver, and sends 10 on the previous channel
+        sender.switch_add(1).send(10)?;
+        // This sends 20 on the current channel
+        sender.send(20)?;
+        // This recieves 20 on the current channel, and switches back to the previous one
+        assert_eq!(20, receiver.switch_add(1).try_recv().ok().unwrap());
+        // This recieves the 10 we originally sent
+        assert_eq!(10, receiver.try_recv().ok().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_consumers(){
+        let (sender, receiver) = diunbounded::<usize>();
+        let workers: Vec<_> = (0..4).map(|_| {
+            let receiver = receiver.clone();
+            thread::spawn(move || receiver.iter().count())
+        }).collect();
+
+        for i in 0..1000{
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+
+        let received: usize = workers.into_iter().map(|w| w.join().unwrap()).sum();
+        assert_eq!(1000, received);
+    }
+
+    struct NoClone;
+
+    #[test]
+    fn clone_test(){
+        let (sender, receiver) = diunbounded::<NoClone>();
+        let _sender2 = sender.clone();
+        let _receiver2 = receiver.clone();
+    }
+}
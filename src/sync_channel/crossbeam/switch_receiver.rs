@@ -0,0 +1,158 @@
+use std::sync::Arc;
+use crossbeam_channel::Receiver;
+use crate::{PERMITTED, SwitchCounter, SwitchOp, SwitchOutcome, SwitchState, switch_lane, update_lane};
+use crate::{Switcher, err::recv::{RecvError, TryRecvError}};
+
+pub struct SwitchReceiver<T, const N: usize, const P: bool>{
+    pub(crate) count: Arc<SwitchCounter>,
+    pub(crate) receivers: [Receiver<T>; N],
+}
+
+impl<T, const N: usize, const P: bool> SwitchReceiver<T, N, P>{
+    /// Try to receive from the activate channel.
+    pub fn try_recv(&self) -> Result<T, TryRecvError>{
+        Ok(self.receivers[self.count.lane(N)].try_recv()?)
+    }
+
+    /// receive from the activate channel.
+    pub fn recv(&self) -> Result<T, RecvError>{
+        Ok(self.receivers[self.count.lane(N)].recv()?)
+    }
+
+    pub fn iter(&self) -> SwitchReceiverGuardIterator<'_, T>{
+        SwitchReceiverGuardIterator{
+            receiver: &self.receivers[self.count.lane(N)]
+        }
+    }
+
+    pub fn try_iter(&self) -> SwitchReceiverGuardTryIterator<'_, T>{
+        SwitchReceiverGuardTryIterator{
+            receiver: &self.receivers[self.count.lane(N)]
+        }
+    }
+
+    /// Check if the activate channel is empty.
+    pub fn is_empty(&self) -> bool{
+        self.receivers[self.count.lane(N)].is_empty()
+    }
+
+    /// Check if the activate channel is full, unbounded channels are never full.
+    pub fn is_full(&self) -> bool{
+        self.receivers[self.count.lane(N)].is_full()
+    }
+
+    /// Returns the number of messages in the activate channel.
+    pub fn len(&self) -> usize{
+        self.receivers[self.count.lane(N)].len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        // These are only allowed be constructed with uniform capacity,
+        // so you can just get the capacity from any instance.
+        self.receivers[0].capacity()
+    }
+
+    pub fn get_guard(&self) -> SwitchReceiverGuard<'_, T>{
+        SwitchReceiverGuard{
+            receiver: &self.receivers[self.count.lane(N)]
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SwitchReceiverGuard<'a, T>{
+    receiver: &'a Receiver<T>
+}
+
+impl<'a, T> SwitchReceiverGuard<'a, T>{
+    /// Try to receive from the activate channel.
+    pub fn try_recv(&self) -> Result<T, TryRecvError>{
+        Ok(self.receiver.try_recv()?)
+    }
+
+    /// receive from the activate channel.
+    pub fn recv(&'_ self) -> Result<T, RecvError>{
+        Ok(self.receiver.recv()?)
+    }
+
+    pub fn iter(&self) -> SwitchReceiverGuardIterator<'_, T>{
+        SwitchReceiverGuardIterator{
+            receiver: self.receiver
+        }
+    }
+
+    pub fn try_iter(&self) -> SwitchReceiverGuardTryIterator<'_, T>{
+        SwitchReceiverGuardTryIterator{
+            receiver: self.receiver
+        }
+    }
+
+    /// Check if the activate channel is empty.
+    pub fn is_empty(&self) -> bool{
+        self.receiver.is_empty()
+    }
+
+    /// Check if the activate channel is full, unbounded channels are never full.
+    pub fn is_full(&self) -> bool{
+        self.receiver.is_full()
+    }
+
+    /// Returns the number of messages in the activate channel.
+    pub fn len(&self) -> usize{
+        self.receiver.len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.receiver.capacity()
+    }
+}
+
+pub struct SwitchReceiverGuardIterator<'a, T>{
+    receiver: &'a Receiver<T>,
+}
+
+impl<'a, T> std::iter::Iterator for SwitchReceiverGuardIterator<'a, T>{
+
+    type Item = T;
+    fn next(&mut self) -> std::option::Option<<Self as std::iter::Iterator>::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+pub struct SwitchReceiverGuardTryIterator<'a, T>{
+    receiver: &'a Receiver<T>,
+}
+
+impl<'a, T> std::iter::Iterator for SwitchReceiverGuardTryIterator<'a, T>{
+
+    type Item = T;
+    fn next(&mut self) -> std::option::Option<<Self as std::iter::Iterator>::Item> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchReceiver<T, N, PERMITTED>{
+    type Output = SwitchReceiverGuard<'a, T>;
+
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<SwitchReceiverGuard<'a, T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state: SwitchState| SwitchReceiverGuard{
+            receiver: &self.receivers[state.lane]
+        })
+    }
+
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<SwitchReceiverGuard<'a, T>, SwitchReceiverGuard<'a, T>>{
+        let guard = |state: SwitchState| SwitchReceiverGuard{
+            receiver: &self.receivers[state.lane]
+        };
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
+
+impl<T, const N: usize, const P: bool> Clone for SwitchReceiver<T, N, P>{
+    fn clone(&self) -> Self{
+        Self{
+            count: self.count.clone(),
+            receivers: self.receivers.clone(),
+        }
+    }
+}
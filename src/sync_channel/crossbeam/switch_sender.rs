@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use crossbeam_channel::Sender;
+use crate::{PERMITTED, SwitchCounter, SwitchOp, SwitchOutcome, SwitchState, switch_lane, update_lane};
+use crate::{Switcher, err::send::{SendError, TrySendError}};
+
+pub struct SwitchSender<T, const N: usize, const P: bool>{
+    pub(crate) count: Arc<SwitchCounter>,
+    pub(crate) senders: [Sender<T>; N],
+}
+
+impl<T, const N: usize, const P: bool> SwitchSender<T, N, P>{
+    pub fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
+        Ok(self.senders[self.count.lane(N)].send(msg)?)
+    }
+
+    pub fn try_send(&'_ self, msg: T) -> Result<(), TrySendError<T>>{
+        Ok(self.senders[self.count.lane(N)].try_send(msg)?)
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.senders[self.count.lane(N)].is_empty()
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.senders[self.count.lane(N)].is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.senders[self.count.lane(N)].len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.senders[0].capacity()
+    }
+}
+
+#[derive(Clone)]
+pub struct SwitchSenderGuard<'a, T>{
+    sender: &'a Sender<T>
+}
+
+impl<'a, T> SwitchSenderGuard<'a, T>{
+    pub fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
+        Ok(self.sender.send(msg)?)
+    }
+
+    pub fn try_send(&'_ self, msg: T) -> Result<(), TrySendError<T>>{
+        Ok(self.sender.try_send(msg)?)
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.sender.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.sender.is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.sender.len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.sender.capacity()
+    }
+}
+
+impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchSender<T, N, PERMITTED>{
+    type Output = SwitchSenderGuard<'a, T>;
+
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<SwitchSenderGuard<'a, T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state: SwitchState| SwitchSenderGuard{
+            sender: &self.senders[state.lane]
+        })
+    }
+
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<SwitchSenderGuard<'a, T>, SwitchSenderGuard<'a, T>>{
+        let guard = |state: SwitchState| SwitchSenderGuard{
+            sender: &self.senders[state.lane]
+        };
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
+
+impl<T, const N: usize, const P: bool> Clone for SwitchSender<T, N, P>{
+    fn clone(&self) -> Self{
+        Self{
+            count: self.count.clone(),
+            senders: self.senders.clone(),
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, atomic::{AtomicUsize, Ordering}};
+use std::time::{Duration, Instant};
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+use event_listener::{Event, EventListener};
+use crate::Lanes;
+use crate::lane_table::{LaneTable, TableLane};
+pub(crate) use crate::lane_table::Handles;
+use crate::err::{recv::{RecvError, RecvTimeoutError, TryRecvError}, send::{SendError, SendTimeoutError, TrySendError}};
+
+enum LaneSender<T>{
+    Unbounded(Sender<T>),
+    Bounded(SyncSender<T>),
+}
+
+/// A single lane of a std switch channel.
+///
+/// `std::sync::mpsc` can't be closed or asked how many messages it holds,
+/// so the lane keeps track of that itself. The lane owns the only sender of its mpsc channel,
+/// and closing the lane drops it. Nobody ever blocks inside mpsc itself, blocking sends and
+/// receives wait on the lane's events instead, so they can give up at a deadline.
+///
+/// A lane with a capacity of 0 hands each message over: a blocking send puts its message in
+/// and then waits until a receiver has taken it, and `try_send` only succeeds while a receiver is waiting.
+pub(crate) struct Lane<T>{
+    sender: RwLock<Option<LaneSender<T>>>,
+    receiver: Mutex<Receiver<T>>,
+    len: AtomicUsize,
+    capacity: Option<usize>,
+    // Notified whenever a message is taken out of the lane, or the lane is closed.
+    space: Event,
+    // Notified whenever a message is put into the lane, or the lane is closed.
+    sent: Event,
+    // The same as `sent`, but every listener is woken up, since selects don't take the message.
+    ready: Event,
+    // The number of messages ever taken out of the lane, only counted with the receiver locked.
+    taken: AtomicUsize,
+    // Notified whenever a message is taken out of the lane, or the lane is closed, for senders waiting on a handover.
+    handed_over: Event,
+    // The number of receivers blocked waiting for a message.
+    waiting: AtomicUsize,
+}
+
+impl<T> Lane<T>{
+    pub(crate) fn unbounded() -> Self{
+        let (sender, receiver) = std::sync::mpsc::channel();
+        Self::new(LaneSender::Unbounded(sender), receiver, None)
+    }
+
+    pub(crate) fn bounded(cap: usize) -> Self{
+        // A rendezvous lane still needs room for the message that's being handed over.
+        let (sender, receiver) = std::sync::mpsc::sync_channel(cap.max(1));
+        Self::new(LaneSender::Bounded(sender), receiver, Some(cap))
+    }
+
+    /// A bounded lane with room for `capacity` messages, or an unbounded one.
+    pub(crate) fn with_capacity(capacity: Option<usize>) -> Self{
+        match capacity{
+            Some(cap) => Self::bounded(cap),
+            None => Self::unbounded(),
+        }
+    }
+
+    fn new(sender: LaneSender<T>, receiver: Receiver<T>, capacity: Option<usize>) -> Self{
+        Self{
+            sender: RwLock::new(Some(sender)),
+            receiver: Mutex::new(receiver),
+            len: AtomicUsize::new(0),
+            capacity,
+            space: Event::new(),
+            sent: Event::new(),
+            ready: Event::new(),
+            taken: AtomicUsize::new(0),
+            handed_over: Event::new(),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    fn is_rendezvous(&self) -> bool{
+        self.capacity == Some(0)
+    }
+
+    pub(crate) fn try_send(&self, msg: T) -> Result<(), TrySendError<T>>{
+        // Without a receiver waiting to take it, the message couldn't be handed over straight away.
+        if self.is_rendezvous() && self.waiting.load(Ordering::SeqCst) <= self.len() && !self.is_closed(){
+            return Err(TrySendError::Full(msg));
+        }
+        self.put(msg)
+    }
+
+    /// Puts `msg` into the lane if there's room, whether or not the lane hands its messages over.
+    fn put(&self, msg: T) -> Result<(), TrySendError<T>>{
+        let sender = self.sender.read().unwrap();
+        // Count the message before it's sent, so a receiver can never take it out first.
+        self.len.fetch_add(1, Ordering::SeqCst);
+        let sent = match sender.as_ref(){
+            Some(LaneSender::Unbounded(sender)) => sender.send(msg).map_err(|err| TrySendError::Closed(err.0)),
+            Some(LaneSender::Bounded(sender)) => sender.try_send(msg).map_err(TrySendError::from),
+            None => Err(TrySendError::Closed(msg)),
+        };
+        match sent{
+            Ok(()) => {
+                self.sent.notify_additional(1);
+                self.ready.notify(usize::MAX);
+            },
+            Err(_) => {
+                self.len.fetch_sub(1, Ordering::SeqCst);
+            },
+        }
+        sent
+    }
+
+    pub(crate) fn send(&self, mut msg: T) -> Result<(), SendError<T>>{
+        loop{
+            // Start listening before trying, so room made in between isn't missed.
+            let listener = self.space.listen();
+            match self.put_for_hand_over(msg){
+                Ok(None) => return Ok(()),
+                Ok(Some(ticket)) => return self.hand_over(ticket, None).map_err(SendError),
+                Err(TrySendError::Closed(returned)) => return Err(SendError(returned)),
+                Err(TrySendError::Full(returned)) => msg = returned,
+            }
+            listener.wait();
+        }
+    }
+
+    pub(crate) fn send_timeout(&self, msg: T, timeout: Duration) -> Result<(), SendTimeoutError<T>>{
+        match Instant::now().checked_add(timeout){
+            Some(deadline) => self.send_deadline(msg, deadline),
+            // Too far in the future to ever time out.
+            None => Ok(self.send(msg)?),
+        }
+    }
+
+    pub(crate) fn send_deadline(&self, mut msg: T, deadline: Instant) -> Result<(), SendTimeoutError<T>>{
+        loop{
+            let listener = self.space.listen();
+            match self.put_for_hand_over(msg){
+                Ok(None) => return Ok(()),
+                Ok(Some(ticket)) => return self.hand_over(ticket, Some(deadline)).map_err(SendTimeoutError::Timeout),
+                Err(TrySendError::Closed(returned)) => return Err(SendTimeoutError::Closed(returned)),
+                Err(TrySendError::Full(returned)) => msg = returned,
+            }
+            if !listener.wait_deadline(deadline){
+                return Err(SendTimeoutError::Timeout(msg));
+            }
+        }
+    }
+
+    /// Puts `msg` into the lane like `put`, returning the ticket to wait for if the lane hands its messages over.
+    fn put_for_hand_over(&self, msg: T) -> Result<Option<usize>, TrySendError<T>>{
+        if !self.is_rendezvous(){
+            return self.put(msg).map(|()| None);
+        }
+        // With the receiver locked, nothing can be taken between putting the message in and reading the count.
+        let _receiver = self.lock_receiver();
+        self.put(msg)?;
+        Ok(Some(self.taken.load(Ordering::SeqCst) + 1))
+    }
+
+    /// Waits until `ticket` messages have been taken, so the one that was just put in has been handed over,
+    /// or the lane is closed. At `deadline` the message is taken back out and returned, unless a receiver has it by then.
+    fn hand_over(&self, ticket: usize, deadline: Option<Instant>) -> Result<(), T>{
+        loop{
+            let listener = self.handed_over.listen();
+            // A closed lane keeps the message for its receivers, but nothing waits on the hand over anymore.
+            if self.taken.load(Ordering::SeqCst) >= ticket || self.is_closed(){
+                return Ok(());
+            }
+            match deadline{
+                None => listener.wait(),
+                Some(deadline) => if !listener.wait_deadline(deadline){
+                    let receiver = self.lock_receiver();
+                    if self.taken.load(Ordering::SeqCst) >= ticket{
+                        return Ok(());
+                    }
+                    // There's only ever room for one message, so the one still in the lane is this one.
+                    let msg = receiver.try_recv().expect("the message hasn't been taken");
+                    drop(receiver);
+                    self.len.fetch_sub(1, Ordering::SeqCst);
+                    self.space.notify_additional(1);
+                    return Err(msg);
+                },
+            }
+        }
+    }
+
+    fn lock_receiver(&self) -> MutexGuard<'_, Receiver<T>>{
+        // The lock is only ever held for a `try_recv`, so this never waits for long.
+        self.receiver.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub(crate) fn try_recv(&self) -> Result<T, TryRecvError>{
+        let receiver = self.lock_receiver();
+        let msg = receiver.try_recv()?;
+        // Counted with the receiver locked, so a sender waiting on a hand over knows which message was taken.
+        self.taken.fetch_add(1, Ordering::SeqCst);
+        drop(receiver);
+        self.received();
+        Ok(msg)
+    }
+
+    pub(crate) fn recv(&self) -> Result<T, RecvError>{
+        loop{
+            // Start listening before trying, so a message sent in between isn't missed.
+            let listener = self.sent.listen();
+            match self.try_recv(){
+                Ok(msg) => return Ok(msg),
+                Err(TryRecvError::Closed) => return Err(RecvError),
+                Err(TryRecvError::Empty) => {
+                    self.waiting.fetch_add(1, Ordering::SeqCst);
+                    listener.wait();
+                    self.waiting.fetch_sub(1, Ordering::SeqCst);
+                },
+            }
+        }
+    }
+
+    pub(crate) fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError>{
+        match Instant::now().checked_add(timeout){
+            Some(deadline) => self.recv_deadline(deadline),
+            // Too far in the future to ever time out.
+            None => Ok(self.recv()?),
+        }
+    }
+
+    pub(crate) fn recv_deadline(&self, deadline: Instant) -> Result<T, RecvTimeoutError>{
+        loop{
+            let listener = self.sent.listen();
+            match self.try_recv(){
+                Ok(msg) => return Ok(msg),
+                Err(TryRecvError::Closed) => return Err(RecvTimeoutError::Closed),
+                Err(TryRecvError::Empty) => {
+                    self.waiting.fetch_add(1, Ordering::SeqCst);
+                    let sent = listener.wait_deadline(deadline);
+                    self.waiting.fetch_sub(1, Ordering::SeqCst);
+                    if !sent{
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                },
+            }
+        }
+    }
+
+    fn received(&self){
+        self.len.fetch_sub(1, Ordering::SeqCst);
+        self.space.notify_additional(1);
+        self.handed_over.notify(usize::MAX);
+    }
+
+    /// Closes the lane, returning `true` if it wasn't already closed.
+    pub(crate) fn close(&self) -> bool{
+        let closed = self.sender.write().unwrap().take().is_some();
+        self.space.notify(usize::MAX);
+        self.sent.notify(usize::MAX);
+        self.ready.notify(usize::MAX);
+        self.handed_over.notify(usize::MAX);
+        closed
+    }
+
+    /// Whether a receive wouldn't block, because there is a message or the lane is closed.
+    pub(crate) fn is_ready(&self) -> bool{
+        !self.is_empty() || self.is_closed()
+    }
+
+    pub(crate) fn listen_sent(&self) -> EventListener{
+        self.sent.listen()
+    }
+
+    pub(crate) fn listen_ready(&self) -> EventListener{
+        self.ready.listen()
+    }
+
+    pub(crate) fn is_closed(&self) -> bool{
+        self.sender.read().unwrap().is_none()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+
+    pub(crate) fn is_full(&self) -> bool{
+        self.capacity.is_some_and(|cap| self.len() >= cap)
+    }
+
+    pub(crate) fn len(&self) -> usize{
+        self.len.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn capacity(&self) -> Option<usize>{
+        self.capacity
+    }
+}
+
+impl<T> TableLane for Lane<T>{
+    fn new(capacity: Option<usize>) -> Self{
+        Self::with_capacity(capacity)
+    }
+
+    fn close(&self) -> bool{
+        Lane::close(self)
+    }
+}
+
+/// Closes every lane, returning `true` if this call closed any of them.
+pub(crate) fn close_lanes<T>(lanes: &[Lane<T>]) -> bool{
+    // Any number of threads could call close, but only one
+    // will be the first to close each lane. Every lane has
+    // to be closed, so this can't stop at the first one.
+    let mut closed = false;
+    for lane in lanes{
+        closed |= lane.close();
+    }
+    closed
+}
+
+/// The lanes an owned guard keeps alive, and closes if it turns out to be the channel's last handle.
+pub(crate) enum OwnedLanes<T>{
+    Fixed(Arc<[Lane<T>]>),
+    // A lane of a table is kept by its slot, since its position can change.
+    Table(Arc<LaneTable<Lane<T>>>, usize),
+}
+
+impl<T> OwnedLanes<T>{
+    /// The lane at position `lane`, or the guard's own lane for a table.
+    pub(crate) fn get(&self, lane: usize) -> &Lane<T>{
+        match self{
+            Self::Fixed(lanes) => &lanes[lane],
+            Self::Table(table, slot) => table.slot(*slot),
+        }
+    }
+
+    /// Where the guard's lane is among the channel's lanes.
+    pub(crate) fn lanes(&self) -> Lanes<'_>{
+        match self{
+            Self::Fixed(lanes) => Lanes::Fixed(lanes.len()),
+            Self::Table(table, slot) => Lanes::Table(table.live_slots(), *slot),
+        }
+    }
+
+    pub(crate) fn close(&self) -> bool{
+        match self{
+            Self::Fixed(lanes) => close_lanes(lanes),
+            Self::Table(table, _) => table.close(),
+        }
+    }
+}
+
+impl<T> Clone for OwnedLanes<T>{
+    fn clone(&self) -> Self{
+        match self{
+            Self::Fixed(lanes) => Self::Fixed(lanes.clone()),
+            Self::Table(table, slot) => Self::Table(table.clone(), *slot),
+        }
+    }
+}
+
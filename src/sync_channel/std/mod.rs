@@ -1,51 +1,107 @@
+mod lane;
 mod switch_receiver;
 mod switch_sender;
 mod switch_sync_sender;
 mod diswitchchannel;
+mod dynswitchchannel;
+mod select;
+mod wait;
 
-pub use switch_receiver::{SwitchReceiver, SwitchReceiverGuard, SwitchReceiverGuardIterator};
-pub use switch_sender::{SwitchSender, SwitchSenderGuard};
-pub use switch_sync_sender::{SwitchSyncSender, SwitchSyncSenderGuard};
+pub use switch_receiver::{SwitchReceiver, SwitchReceiverGuard, OwnedSwitchReceiverGuard, SwitchReceiverGuardIterator, SwitchReceiverGuardTryIterator, SwitchReceiverGuardDrain};
+pub use switch_sender::{SwitchSender, SwitchSenderGuard, OwnedSwitchSenderGuard};
+pub use switch_sync_sender::{SwitchSyncSender, SwitchSyncSenderGuard, OwnedSwitchSyncSenderGuard};
+pub use select::Select;
 pub use diswitchchannel::{dibounded, diunbounded, DiSwitchSender, DiSwitchSyncSender, DiSwitchReceiver};
+pub use dynswitchchannel::{dyn_bounded, dyn_unbounded, DynSwitchSender, DynSwitchReceiver};
 
-use std::convert::TryInto;
-use core::sync::atomic::AtomicUsize;
+use crate::SwitchCounter;
 use std::sync::Arc;
-use core::iter::repeat_with;
+use lane::{Handles, Lane};
+use crate::SwitchGroup;
 use crate::err::send::{SendError, TrySendError};
 
+/// Makes a switch channel with `N` lanes that each hold up to `cap` messages.
+///
+/// With a `cap` of 0, like `std::sync::mpsc::sync_channel(0)`, every send waits until a receiver has taken its message.
 pub fn bounded<T, const N: usize, const S: bool, const P: bool>(cap: usize) -> (SwitchSyncSender<T, N, S>, SwitchReceiver<T, N, P>){
-    use std::sync::mpsc::{sync_channel, SyncSender, Receiver};
-    
-    let (senders, receivers): (Vec<SyncSender<T>>, Vec<Receiver<T>>) = repeat_with(|| sync_channel(cap)).take(N).unzip();
+    let switch = Arc::new(SwitchCounter::new());
+    bounded_with_switches(cap, switch.clone(), switch)
+}
+
+/// Like `bounded`, but the senders and receivers each get their own switch counter,
+/// so switching one half doesn't redirect the other.
+pub fn bounded_decoupled<T, const N: usize, const S: bool, const P: bool>(cap: usize) -> (SwitchSyncSender<T, N, S>, SwitchReceiver<T, N, P>){
+    bounded_with_switches(cap, Arc::new(SwitchCounter::new()), Arc::new(SwitchCounter::new()))
+}
+
+pub fn unbounded<T, const N: usize, const S: bool, const P: bool>() -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    let switch = Arc::new(SwitchCounter::new());
+    unbounded_with_switches(switch.clone(), switch)
+}
+
+/// Like `unbounded`, but the senders and receivers each get their own switch counter,
+/// so switching one half doesn't redirect the other.
+pub fn unbounded_decoupled<T, const N: usize, const S: bool, const P: bool>() -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    unbounded_with_switches(Arc::new(SwitchCounter::new()), Arc::new(SwitchCounter::new()))
+}
+
+/// Like `bounded`, but every lane gets its own capacity, `None` for an unbounded lane.
+///
+/// `bounded_with([64, 4096])` makes a small first lane and a large second one,
+/// while `bounded_with([Some(64), None])` leaves the second lane unbounded.
+pub fn bounded_with<T, const N: usize, const S: bool, const P: bool, C: Into<Option<usize>>>(caps: [C; N]) -> (SwitchSyncSender<T, N, S>, SwitchReceiver<T, N, P>){
+    let switch = Arc::new(SwitchCounter::new());
+    with_lanes(caps.map(|cap| Lane::with_capacity(cap.into())), switch.clone(), switch)
+}
+
+/// Like `bounded`, but the channel switches together with every other channel in `group`.
+pub fn bounded_in<T, const N: usize, const S: bool, const P: bool>(group: &SwitchGroup<N>, cap: usize) -> (SwitchSyncSender<T, N, S>, SwitchReceiver<T, N, P>){
+    bounded_with_switches(cap, group.count.clone(), group.count.clone())
+}
+
+/// Like `unbounded`, but the channel switches together with every other channel in `group`.
+pub fn unbounded_in<T, const N: usize, const S: bool, const P: bool>(group: &SwitchGroup<N>) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    unbounded_with_switches(group.count.clone(), group.count.clone())
+}
+
+pub(crate) fn bounded_with_switches<T, const N: usize, const S: bool, const P: bool>(cap: usize, sender_switch: Arc<SwitchCounter>, receiver_switch: Arc<SwitchCounter>) -> (SwitchSyncSender<T, N, S>, SwitchReceiver<T, N, P>){
+    with_lanes(std::array::from_fn(|_| Lane::bounded(cap)), sender_switch, receiver_switch)
+}
+
+fn with_lanes<T, const N: usize, const S: bool, const P: bool>(lanes: [Lane<T>; N], sender_switch: Arc<SwitchCounter>, receiver_switch: Arc<SwitchCounter>) -> (SwitchSyncSender<T, N, S>, SwitchReceiver<T, N, P>){
+    let lanes = Arc::new(lanes);
+    let handles = Arc::new(Handles::new());
 
-    let switch = Arc::new(AtomicUsize::new(0));
     (
         SwitchSyncSender{
-            count: switch.clone(),
-            senders: senders.try_into().unwrap()
+            count: sender_switch,
+            lanes: lanes.clone(),
+            handles: handles.clone(),
         },
         SwitchReceiver{
-            count: switch.clone(),
-            receivers: receivers.try_into().unwrap()
+            count: receiver_switch,
+            lanes,
+            handles,
+            rotation: None,
         }
     )
 }
 
-pub fn unbounded<T, const N: usize, const S: bool, const P: bool>() -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
-    use std::sync::mpsc::{channel, Sender, Receiver};
-    
-    let (senders, receivers): (Vec<Sender<T>>, Vec<Receiver<T>>) = repeat_with(|| channel()).take(N).unzip();
+pub(crate) fn unbounded_with_switches<T, const N: usize, const S: bool, const P: bool>(sender_switch: Arc<SwitchCounter>, receiver_switch: Arc<SwitchCounter>) -> (SwitchSender<T, N, S>, SwitchReceiver<T, N, P>){
+    let lanes = Arc::new(std::array::from_fn(|_| Lane::unbounded()));
+    let handles = Arc::new(Handles::new());
 
-    let switch = Arc::new(AtomicUsize::new(0));
     (
         SwitchSender{
-            count: switch.clone(),
-            senders: senders.try_into().unwrap()
+            count: sender_switch,
+            lanes: lanes.clone(),
+            handles: handles.clone(),
         },
         SwitchReceiver{
-            count: switch.clone(),
-            receivers: receivers.try_into().unwrap()
+            count: receiver_switch,
+            lanes,
+            handles,
+            rotation: None,
         }
     )
 }
@@ -64,4 +120,757 @@ impl<T> From<std::sync::mpsc::TrySendError<T>> for TrySendError<T>{
             std::sync::mpsc::TrySendError::Disconnected(t) => Self::Closed(t),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::*;
+    use super::*;
+
+    #[test]
+    fn unbounded_send_switch_receive() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 2, false, true>();
+        sender.send(10)?;
+        assert_eq!(10, receiver.switch_add(1).try_recv().ok().unwrap());
+        assert_eq!(err::recv::TryRecvError::Empty, receiver.try_recv().err().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn switch_add_wraps() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<usize, 3, true, true>();
+        for i in 0..3000{
+            // Each message is sent on lane i % 3 before switching to the next one.
+            sender.switch_add(1).send(i)?;
+        }
+        for lane in 0..3{
+            let guard = receiver.switch_add(1);
+            for i in (lane..3000).step_by(3){
+                assert_eq!(Ok(i), guard.try_recv());
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn switch_sub_underflow_wraps() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 3, false, true>();
+        // Subtracting from lane 0 wraps around to the last lane.
+        receiver.switch_sub(1);
+        sender.send(10)?;
+        assert_eq!(Ok(10), receiver.switch_sub(1).try_recv());
+        sender.send(20)?;
+        assert_eq!(Ok(20), receiver.switch_sub(4).try_recv());
+        sender.send(30)?;
+        assert_eq!(Ok(30), receiver.get_guard().try_recv());
+        Ok(())
+    }
+
+    #[test]
+    fn switch_outcome_returns_both_lanes() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 3, false, true>();
+        sender.send(10)?;
+        let outcome = receiver.switch_outcome(SwitchOp::Sub(1));
+        assert_eq!((0, 2), (outcome.previous_lane, outcome.current_lane));
+        assert!(outcome.switched());
+        sender.send(20)?;
+        assert_eq!(Ok(10), outcome.previous.try_recv());
+        assert_eq!(Ok(20), outcome.current.try_recv());
+
+        let outcome = receiver.switch_outcome(SwitchOp::Max(2));
+        assert_eq!((2, 2), (outcome.previous_lane, outcome.current_lane));
+        assert!(!outcome.switched());
+        Ok(())
+    }
+
+    #[test]
+    fn switch_compare_exchange() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = diunbounded::<u32>();
+        sender.send(10)?;
+        assert_eq!(Ok(10), receiver.switch_compare_exchange(0, 1).ok().unwrap().try_recv());
+        sender.send(20)?;
+        // The channel has already been switched away from lane 0,
+        // so this doesn't switch and hands back the lane it found instead.
+        assert_eq!(Ok(20), receiver.switch_compare_exchange(0, 1).err().unwrap().try_recv());
+        // Lane 2 wraps back around to lane 0.
+        assert!(receiver.switch_update(|lane| if lane == 1 { Some(lane + 1) } else { None }).is_ok());
+        sender.send(30)?;
+        assert_eq!(Ok(30), receiver.switch_compare_exchange(1, 0).err().unwrap().try_recv());
+        Ok(())
+    }
+
+    #[test]
+    fn guard_for_lane() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded::<u32, 2, true, true>(1);
+        sender.switch_xor(1);
+        // Writing to lane 0 doesn't depend on, or change, the active lane.
+        sender.send_to(0)?.send(10)?;
+        sender.send(20)?;
+        assert_eq!(Ok(10), receiver.recv_from(0)?.try_recv());
+        assert_eq!(Ok(20), receiver.try_recv());
+        assert_eq!(err::lane::OutOfRangeError{ lane: 2, lanes: 2 }, sender.guard_for(2).err().unwrap());
+        assert!(receiver.guard_for(usize::MAX).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn decoupled_double_buffer() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded_decoupled::<u32, 2, true, true>();
+        sender.send(10)?;
+        // Switching the receiver leaves the sender writing to lane 0.
+        let drained = receiver.switch_xor(1);
+        sender.send(20)?;
+        assert_eq!(vec![10, 20], drained.try_iter().collect::<Vec<_>>());
+        assert!(receiver.try_recv().is_err());
+
+        // Flip the sender so the receiver can drain lane 0 while lane 1 fills up.
+        sender.switch_xor(1);
+        sender.send(30)?;
+        assert_eq!(Ok(30), receiver.try_recv());
+
+        let (sender, receiver) = bounded_decoupled::<u32, 2, true, true>(1);
+        receiver.switch_xor(1);
+        sender.try_send(10)?;
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(Ok(10), receiver.recv_from(0)?.try_recv());
+        Ok(())
+    }
+
+    #[test]
+    fn switch_group_switches_every_channel() -> Result<(), Box<dyn std::error::Error>>{
+        let group = SwitchGroup::<2>::new();
+        let (add_sender, add_receiver) = unbounded_in::<usize, 2, false, true>(&group);
+        let (para_sender, para_receiver) = bounded_in::<(), 2, false, true>(&group, 10);
+        add_sender.send(1)?;
+        para_sender.send(())?;
+
+        // Switching one receiver moves every channel in the group.
+        let add = add_receiver.switch_xor(1);
+        assert_eq!(1, group.lane());
+        assert!(para_receiver.try_recv().is_err());
+        assert_eq!(Ok(1), add.try_recv());
+
+        add_sender.send(2)?;
+        assert_eq!((1, 0), group.switch(SwitchOp::Xor(1)));
+        assert_eq!(Ok(()), para_receiver.try_recv());
+        assert_eq!(Ok(2), add_receiver.recv_from(1)?.try_recv());
+        Ok(())
+    }
+
+    #[test]
+    fn introspection() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded::<u32, 2, true, true>(2);
+        assert!(receiver.is_empty() && sender.is_empty());
+        assert_eq!(Some(2), receiver.capacity());
+        sender.send(10)?;
+        sender.send(20)?;
+        assert!(receiver.is_full() && sender.is_full());
+        assert_eq!(2, receiver.len());
+        assert!(sender.try_send(30).is_err());
+        // Only the active lane is counted.
+        assert_eq!(0, sender.send_to(1)?.len());
+        assert_eq!(10, receiver.recv()?);
+        assert_eq!(1, sender.len());
+
+        let (sender, receiver) = unbounded::<u32, 2, false, true>();
+        assert_eq!(None, sender.capacity());
+        assert!(!sender.is_full());
+        assert_eq!((1, 1), (sender.sender_count(), receiver.receiver_count()));
+        let senders = vec![sender.clone(), sender.clone()];
+        let other = receiver.clone();
+        assert_eq!((3, 2), (receiver.sender_count(), sender.receiver_count()));
+        drop(senders);
+        drop(other);
+        assert_eq!((1, 1), (receiver.get_guard().sender_count(), sender.receiver_count()));
+        Ok(())
+    }
+
+    #[test]
+    fn close_wakes_blocked_receiver() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<u32, 2, false, true>();
+        sender.send(10)?;
+        let closer = receiver.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            assert!(closer.close());
+        });
+        // Messages sent before closing can still be received.
+        assert_eq!(10, receiver.recv()?);
+        assert!(receiver.recv().is_err());
+        handle.join().unwrap();
+        assert!(receiver.is_closed() && sender.is_closed());
+        assert!(!sender.close());
+        assert!(sender.send(20).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn close_wakes_blocked_sender() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = dibounded::<u32>(1);
+        sender.send(10)?;
+        let handle = std::thread::spawn(move || sender.send(20));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(receiver.close());
+        assert_eq!(err::send::SendError(20), handle.join().unwrap().err().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn blocked_sender_wakes_on_receive() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = dibounded::<u32>(1);
+        sender.send(10)?;
+        let handle = std::thread::spawn(move || sender.send(20));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(10, receiver.recv()?);
+        handle.join().unwrap()?;
+        assert_eq!(20, receiver.recv()?);
+        Ok(())
+    }
+
+    #[test]
+    fn dropping_a_side_closes_the_channel(){
+        let (sender, receiver) = unbounded::<u32, 2, false, true>();
+        let clone = sender.clone();
+        drop(sender);
+        assert!(!receiver.is_closed());
+        drop(clone);
+        assert!(receiver.is_closed());
+        assert_eq!(err::recv::TryRecvError::Closed, receiver.try_recv().err().unwrap());
+
+        let (sender, receiver) = bounded::<u32, 2, false, true>(1);
+        drop(receiver);
+        assert!(sender.is_closed());
+        assert!(sender.try_send(10).is_err());
+    }
+
+    #[test]
+    fn recv_timeout_and_deadline() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::{Duration, Instant};
+        use err::recv::RecvTimeoutError;
+
+        let (sender, receiver) = unbounded::<u32, 2, false, true>();
+        let start = Instant::now();
+        assert_eq!(RecvTimeoutError::Timeout, receiver.recv_timeout(Duration::from_millis(20)).err().unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(RecvTimeoutError::Timeout, receiver.get_guard().recv_deadline(Instant::now()).err().unwrap());
+
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            sender.send(10)
+        });
+        assert_eq!(10, receiver.recv_timeout(Duration::from_secs(10))?);
+        handle.join().unwrap()?;
+        // The sender was dropped with the thread, which closes the channel.
+        assert_eq!(RecvTimeoutError::Closed, receiver.recv_from(1)?.recv_timeout(Duration::MAX).err().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn send_timeout_hands_message_back() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::{Duration, Instant};
+        use err::send::SendTimeoutError;
+
+        let (sender, receiver) = bounded::<u32, 2, false, true>(1);
+        sender.send_timeout(10, Duration::from_millis(10))?;
+        assert_eq!(SendTimeoutError::Timeout(20), sender.send_timeout(20, Duration::from_millis(20)).err().unwrap());
+        // Other lanes still have room.
+        sender.send_to(1)?.send_deadline(30, Instant::now())?;
+
+        let handle = std::thread::spawn(move || sender.send_timeout(20, Duration::from_secs(10)).map(|_| sender));
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(10, receiver.recv()?);
+        let sender = handle.join().unwrap()?;
+        assert_eq!(20, receiver.recv()?);
+
+        sender.send(40)?;
+        let handle = std::thread::spawn(move || sender.send_timeout(50, Duration::MAX));
+        std::thread::sleep(Duration::from_millis(20));
+        drop(receiver);
+        assert_eq!(SendTimeoutError::Closed(50), handle.join().unwrap().err().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn select_reports_ready_receiver() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::Duration;
+
+        let (add_sender, add_receiver) = diunbounded::<usize>();
+        let (para_sender, para_receiver) = dibounded::<()>(1);
+        let mut select = Select::new();
+        let add = select.recv(&add_receiver);
+        let para = select.recv(&para_receiver);
+        let priority = select.recv_from(&add_receiver, 1)?;
+        assert!(select.recv_from(&add_receiver, 2).is_err());
+        assert_eq!(None, select.try_ready());
+        assert_eq!(None, select.ready_timeout(Duration::from_millis(20)));
+
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            para_sender.send(())
+        });
+        assert_eq!(para, select.ready());
+        handle.join().unwrap()?;
+        // Selecting doesn't take the message, and the sender being dropped closed the channel.
+        assert_eq!(para, select.ready());
+        assert_eq!(Ok(()), para_receiver.try_recv());
+
+        add_sender.send_to(1)?.send(1)?;
+        assert_eq!(priority, select.ready());
+        add_receiver.switch();
+        // Both are ready now, so they take turns.
+        assert_eq!(add, select.ready());
+        assert_eq!(para, select.ready());
+        assert_eq!(priority, select.ready());
+        assert_eq!(Ok(1), add_receiver.try_recv());
+        Ok(())
+    }
+
+    #[test]
+    fn owned_guards_move_into_threads() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded_decoupled::<u32, 2, true, true>(10);
+        let outcome = sender.switch_outcome_owned(SwitchOp::Xor(1));
+        assert_eq!((0, 1), (outcome.previous.lane(), outcome.current.lane()));
+        let producer = outcome.previous;
+        std::thread::spawn(move || producer.send(10)).join().unwrap()?;
+
+        // Lane 0 is drained on another thread, while lane 1 keeps being used.
+        let drained = receiver.switch_update_owned(|lane| Some(lane + 1)).ok().unwrap();
+        assert_eq!(0, drained.lane());
+        assert_eq!(2, receiver.receiver_count());
+        let handle = std::thread::spawn(move || drained.iter().collect::<Vec<_>>());
+        sender.send(20)?;
+        assert_eq!(20, receiver.get_owned_guard().recv()?);
+        assert!(receiver.owned_guard_for(2).is_err());
+        drop(outcome.current);
+        assert_eq!(1, sender.sender_count());
+        drop(sender);
+        assert_eq!(vec![10], handle.join().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn drain_until_switched_away() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::Duration;
+
+        let (sender, receiver) = diunbounded::<u32>();
+        let guard = receiver.get_owned_guard();
+        let handle = std::thread::spawn(move || guard.drain().collect::<Vec<_>>());
+        sender.send(1)?;
+        std::thread::sleep(Duration::from_millis(20));
+        // The lane is empty but still active, so the drain waits for this one.
+        sender.send(2)?;
+        std::thread::sleep(Duration::from_millis(20));
+        let guard = receiver.switch();
+        assert!(!guard.is_active());
+        assert_eq!(vec![1, 2], handle.join().unwrap());
+
+        sender.send(3)?;
+        assert!(receiver.get_guard().is_active());
+        // Lane 0 is empty and inactive, so draining it ends straight away.
+        assert_eq!(0, guard.drain().count());
+        assert_eq!(vec![3], receiver.switch().try_iter().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn drain_keeps_a_message_sent_right_before_the_switch() -> Result<(), Box<dyn std::error::Error>>{
+        // The send and the switch race the drain, which can find the lane empty just before the message
+        // lands and only then see the switch. It has to take the message either way.
+        for _ in 0..200{
+            let (sender, receiver) = diunbounded::<u32>();
+            let guard = receiver.get_owned_guard();
+            let handle = std::thread::spawn(move || guard.drain().collect::<Vec<_>>());
+            for msg in 0..100{
+                sender.send(msg)?;
+            }
+            receiver.switch();
+            assert_eq!((0..100).collect::<Vec<_>>(), handle.join().unwrap());
+            assert_eq!(0, receiver.guard_for(0)?.try_iter().count());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn epochs_track_lane_switches() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded::<u32, 2, true, true>(4);
+        let guard = receiver.get_guard();
+        let sender_guard = sender.guard_for(0)?;
+        assert_eq!((0, 0), (guard.epoch(), receiver.epoch()));
+        assert!(guard.is_current() && sender_guard.is_current());
+
+        // Switching to the lane that's already active doesn't start a new epoch.
+        let outcome = receiver.switch_outcome(SwitchOp::Min(0));
+        assert_eq!((0, 0), (outcome.previous_epoch, outcome.current_epoch));
+        assert!(guard.is_current());
+
+        let outcome = receiver.switch_outcome(SwitchOp::Add(1));
+        assert_eq!((0, 1), (outcome.previous_epoch, outcome.current_epoch));
+        assert_eq!(1, outcome.current.epoch());
+        assert!(outcome.current.is_current());
+        assert!(!guard.is_current() && !sender_guard.is_current());
+
+        // Back on the same lane, but a guard from before the round trip is stale.
+        receiver.switch_add(1);
+        assert!(guard.is_active());
+        assert!(!guard.is_current());
+        assert_eq!(2, sender.epoch());
+        assert!(sender.get_owned_guard().is_current());
+        Ok(())
+    }
+
+    #[test]
+    fn wait_for_switch_follows_another_handle() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::Duration;
+
+        let (sender, receiver) = bounded::<u32, 2, true, true>(4);
+        assert!(receiver.wait_for_switch_timeout(Duration::from_millis(10)).is_none());
+        let guard = receiver.get_owned_guard();
+        assert!(!guard.wait_for_switch_timeout(Duration::from_millis(10)));
+
+        let follower = receiver.clone();
+        let handle = std::thread::spawn(move || {
+            let guard = follower.wait_for_switch();
+            (guard.epoch(), guard.recv())
+        });
+        std::thread::sleep(Duration::from_millis(20));
+        sender.switch_outcome(SwitchOp::Xor(1)).current.send(5)?;
+        assert_eq!((1, Ok(5)), handle.join().unwrap());
+        receiver.switch_xor(1);
+
+        // The guard was taken before both switches, so it doesn't wait at all.
+        guard.wait_for_switch();
+        assert!(guard.is_active() && !guard.is_current());
+        Ok(())
+    }
+
+    #[test]
+    fn select_wakes_on_switch() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::Duration;
+
+        let (sender, receiver) = bounded::<u32, 2, true, true>(4);
+        sender.guard_for(1)?.send(1)?;
+        let switcher = receiver.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            switcher.switch_xor(1);
+        });
+        let mut select = Select::new();
+        select.recv(&receiver);
+        assert_eq!(Some(0), select.ready_timeout(Duration::from_secs(5)));
+        handle.join().unwrap();
+        assert_eq!(Ok(1), receiver.try_recv());
+        Ok(())
+    }
+
+    #[test]
+    fn rotation_on_empty() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded::<u32, 3, true, true>(8);
+        let receiver = receiver.with_rotation(Rotation::OnEmpty);
+        sender.guard_for(0)?.send(1)?;
+        sender.guard_for(0)?.send(2)?;
+        sender.guard_for(2)?.send(3)?;
+        assert_eq!(vec![1, 2, 3], (0..3).map(|_| receiver.recv()).collect::<Result<Vec<_>, _>>()?);
+        assert!(receiver.guard_for(2)?.is_active());
+        assert_eq!(Err(err::recv::TryRecvError::Empty), receiver.try_recv());
+
+        // Nothing is waiting on the active lane, so the blocking receive picks up lane 1.
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            sender.guard_for(1).unwrap().send(4).unwrap();
+        });
+        assert_eq!(Ok(4), receiver.recv());
+        handle.join().unwrap();
+        assert_eq!(Err(err::recv::RecvError), receiver.recv());
+        Ok(())
+    }
+
+    #[test]
+    fn rotation_after_k_messages() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded::<u32, 2, true, true>(8);
+        let receiver = receiver.with_rotation(Rotation::After(2));
+        for msg in 1..=3{
+            sender.guard_for(0)?.send(msg)?;
+            sender.guard_for(1)?.send(msg * 10)?;
+        }
+        let received: Vec<_> = (0..6).map(|_| receiver.try_recv().unwrap()).collect();
+        assert_eq!(vec![1, 2, 10, 20, 3, 30], received);
+        Ok(())
+    }
+
+    #[test]
+    fn weighted_rotation_delivers_ratios() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<usize, 3, true, true>();
+        let receiver = receiver.with_rotation(Rotation::Weighted(vec![5, 3, 2]));
+        for lane in 0..3{
+            for _ in 0..100{
+                sender.send_to(lane)?.send(lane)?;
+            }
+        }
+        let mut served = [0; 3];
+        for _ in 0..100{
+            served[receiver.recv()?] += 1;
+        }
+        assert_eq!([50, 30, 20], served);
+
+        // Once lane 0 runs dry its turns go to the others, still in proportion.
+        for _ in 0..150{
+            served[receiver.recv()?] += 1;
+        }
+        assert_eq!([100, 90, 60], served);
+        Ok(())
+    }
+
+    #[test]
+    fn priority_rotation_with_starvation_guard() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<usize, 3, true, true>();
+        let receiver = receiver.with_rotation(Rotation::Priority(StarvationGuard::Messages(4)));
+        for (lane, messages) in [(0, 10), (1, 3), (2, 3)]{
+            for _ in 0..messages{
+                sender.send_to(lane)?.send(lane)?;
+            }
+        }
+        let served: Vec<_> = (0..16).map(|_| receiver.try_recv().unwrap()).collect();
+        assert_eq!(vec![0, 0, 0, 0, 1, 0, 0, 0, 0, 2, 0, 0, 1, 1, 2, 2], served);
+        // Receiving never switched the channel.
+        assert!(receiver.guard_for(0)?.is_active());
+
+        let (sender, receiver) = unbounded::<usize, 2, true, true>();
+        let receiver = receiver.with_rotation(Rotation::Priority(StarvationGuard::Time(std::time::Duration::from_millis(20))));
+        for lane in [1, 0, 0, 0]{
+            sender.send_to(lane)?.send(lane)?;
+        }
+        assert_eq!(Ok(0), receiver.recv());
+        std::thread::sleep(std::time::Duration::from_millis(30));
+        assert_eq!(Ok(1), receiver.recv());
+        assert_eq!(Ok(0), receiver.recv());
+        Ok(())
+    }
+
+    #[test]
+    fn priority_receives_leave_the_senders_lane_alone() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<usize, 2, true, true>();
+        let receiver = receiver.with_rotation(Rotation::Priority(StarvationGuard::Off));
+        sender.switch_add(1);
+        let epoch = sender.epoch();
+        sender.send_to(0)?.send(0)?;
+        sender.send(1)?;
+        assert_eq!(Ok(0), receiver.try_recv());
+        // The sender still sends into lane 1, even though the receiver just served lane 0.
+        sender.send(1)?;
+        assert_eq!(epoch, sender.epoch());
+        assert_eq!(2, sender.send_to(1)?.len());
+        assert_eq!(Ok(1), receiver.try_recv());
+        assert_eq!(Ok(1), receiver.try_recv());
+        Ok(())
+    }
+
+    #[test]
+    fn starvation_guard_counts_from_a_natural_turn() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = unbounded::<usize, 2, true, true>();
+        let receiver = receiver.with_rotation(Rotation::Priority(StarvationGuard::Messages(2)));
+        sender.send_to(1)?.send(1)?;
+        assert_eq!(Ok(1), receiver.try_recv());
+        for lane in [0, 0, 0, 1]{
+            sender.send_to(lane)?.send(lane)?;
+        }
+        let served: Vec<_> = (0..4).map(|_| receiver.try_recv().unwrap()).collect();
+        assert_eq!(vec![0, 0, 1, 0], served);
+        Ok(())
+    }
+
+    #[test]
+    fn time_sliced_rotation() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::Duration;
+
+        let (sender, receiver) = unbounded::<usize, 2, true, true>();
+        let receiver = receiver.with_rotation(Rotation::TimeSlice(Duration::from_millis(20)));
+        for lane in [0, 0, 0, 1, 1, 0]{
+            sender.send_to(lane)?.send(lane)?;
+        }
+        assert_eq!(vec![0, 0], (0..2).map(|_| receiver.recv()).collect::<Result<Vec<_>, _>>()?);
+        std::thread::sleep(Duration::from_millis(30));
+        // Lane 0 still has messages, but its slice is up.
+        assert_eq!(vec![1, 1], (0..2).map(|_| receiver.recv()).collect::<Result<Vec<_>, _>>()?);
+        // Lane 1 is empty, so lane 0 is served again before the slice is up.
+        assert_eq!(vec![0, 0], (0..2).map(|_| receiver.recv()).collect::<Result<Vec<_>, _>>()?);
+        Ok(())
+    }
+
+    #[test]
+    fn dyn_lanes_from_configuration() -> Result<(), Box<dyn std::error::Error>>{
+        let lanes = 5;
+        let (sender, receiver) = dyn_bounded::<usize, true, true>(lanes, 4);
+        assert_eq!(lanes, receiver.lanes());
+        // Like the fixed size channels, the switch wraps around the number of lanes.
+        sender.switch_add(lanes + 3);
+        sender.send(3)?;
+        assert_eq!(Ok(3), receiver.recv_from(3)?.try_recv());
+        assert!(sender.guard_for(lanes).is_err());
+
+        let receiver = receiver.with_rotation(Rotation::OnEmpty);
+        for lane in 0..lanes{
+            sender.send_to(lane)?.send(lane)?;
+        }
+        let mut received = (0..lanes).map(|_| receiver.recv()).collect::<Result<Vec<_>, _>>()?;
+        received.sort();
+        assert_eq!((0..lanes).collect::<Vec<_>>(), received);
+
+        drop(sender);
+        assert_eq!(Err(err::recv::RecvError), receiver.recv());
+        Ok(())
+    }
+
+    #[test]
+    fn add_and_retire_lanes_live() -> Result<(), Box<dyn std::error::Error>>{
+        use err::lane::{OutOfRangeError, RetireError};
+
+        let (sender, receiver) = dyn_unbounded::<usize, true, true>(2);
+        sender.send_to(1)?.send(10)?;
+        assert_eq!(2, receiver.add_lane());
+        sender.switch_add(2);
+        sender.send(20)?;
+        let retired = sender.send_to(1)?;
+        let epoch = receiver.epoch();
+
+        // Lane 2 moves down to take the retired lane's place, and stays active.
+        sender.retire_lane(1, 0)?;
+        assert_eq!(2, receiver.lanes());
+        assert_ne!(epoch, receiver.epoch());
+        assert!(retired.send(11).is_err());
+        assert_eq!(Ok(10), receiver.recv_from(0)?.try_recv());
+        assert_eq!(Ok(20), receiver.try_recv());
+
+        // Retiring the active lane switches to the lane it's redirected to, messages and all.
+        sender.send(30)?;
+        assert!(receiver.retire_lane(1, 0)?.is_empty());
+        assert_eq!(Ok(30), receiver.try_recv());
+        assert_eq!(Err(RetireError::SameLane(0)), receiver.retire_lane(0, 0));
+        assert_eq!(Err(RetireError::OutOfRange(OutOfRangeError{ lane: 1, lanes: 1 })), receiver.retire_lane(1, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn guards_follow_their_lane_as_lanes_come_and_go() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = dyn_unbounded::<u32, true, true>(4);
+        sender.switch_add(3);
+        let active = receiver.get_guard();
+        let owned = receiver.get_owned_guard();
+        let first = receiver.guard_for(0)?;
+        // Lane 3 moves down to position 2, but it's still the same lane, and still active.
+        sender.retire_lane(1, 0)?;
+        assert!(active.is_active() && owned.is_active());
+        assert!(!first.is_active());
+
+        // A guard taken while there were 4 lanes doesn't mistake lane 4 for lane 0.
+        let (sender, receiver) = dyn_unbounded::<u32, true, true>(4);
+        let first = receiver.guard_for(0)?;
+        assert_eq!(4, sender.add_lane());
+        sender.switch_add(4);
+        assert_eq!(4, receiver.get_owned_guard().lane());
+        assert!(!first.is_active());
+        Ok(())
+    }
+
+    #[test]
+    fn receiver_retires_into_a_full_lane_without_waiting() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = dyn_bounded::<u32, true, true>(2, 1);
+        sender.send(0)?;
+        sender.send_to(1)?.send(1)?;
+        // The lane at `to` is full, so the leftover is handed back instead of waiting for room.
+        assert_eq!(vec![0], receiver.retire_lane(0, 1)?);
+        assert_eq!(1, receiver.lanes());
+        assert_eq!(Ok(1), receiver.try_recv());
+        assert_eq!(Err(err::recv::TryRecvError::Empty), receiver.try_recv());
+        Ok(())
+    }
+
+    #[test]
+    fn blocked_receiver_follows_retired_lane() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = dyn_bounded::<u32, true, true>(1, 1);
+        sender.add_lane();
+        let handle = std::thread::spawn(move || receiver.recv());
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        sender.retire_lane(0, 1)?;
+        sender.send(5)?;
+        assert_eq!(Ok(5), handle.join().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn lanes_with_their_own_capacity() -> Result<(), Box<dyn std::error::Error>>{
+        let (sender, receiver) = bounded_with::<u32, 2, true, true, _>([1, 3]);
+        assert_eq!(Some(1), receiver.capacity());
+        sender.send(10)?;
+        assert!(sender.is_full());
+        assert!(sender.try_send(11).is_err());
+        sender.switch_add(1);
+        assert_eq!(Some(3), sender.capacity());
+        for val in 20..23{
+            sender.try_send(val)?;
+        }
+        assert!(sender.is_full());
+        assert_eq!(Some(1), receiver.recv_from(0)?.capacity());
+
+        // Bounded and unbounded lanes can be mixed.
+        let (sender, receiver) = bounded_with::<u32, 2, true, true, _>([Some(1), None]);
+        assert_eq!((Some(1), None), (sender.send_to(0)?.capacity(), sender.send_to(1)?.capacity()));
+        for val in 0..100{
+            sender.send_to(1)?.try_send(val)?;
+        }
+        assert!(!receiver.recv_from(1)?.is_full());
+        assert_eq!(100, receiver.recv_from(1)?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn bounded_zero_hands_messages_over() -> Result<(), Box<dyn std::error::Error>>{
+        use std::time::{Duration, Instant};
+        use err::send::SendTimeoutError;
+
+        let (sender, receiver) = bounded::<u32, 2, true, true>(0);
+        assert_eq!(Some(0), sender.capacity());
+        // Nobody is waiting to take the message, so it can't be handed over.
+        assert_eq!(Err(TrySendError::Full(1)), sender.try_send(1));
+        assert_eq!(SendTimeoutError::Timeout(2), sender.send_timeout(2, Duration::from_millis(20)).err().unwrap());
+        assert_eq!(Err(err::recv::TryRecvError::Empty), receiver.try_recv());
+
+        let receiver = Arc::new(receiver);
+        let waiting = receiver.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            waiting.recv()
+        });
+        // The send only returns once the receiver has taken the message.
+        let start = Instant::now();
+        sender.send(3)?;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(Ok(3), handle.join().unwrap());
+
+        let waiting = receiver.clone();
+        let handle = std::thread::spawn(move || waiting.recv());
+        std::thread::sleep(Duration::from_millis(20));
+        // With a receiver waiting, a message can be handed straight over.
+        sender.try_send(4)?;
+        assert_eq!(Ok(4), handle.join().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn switch_loop_never_panics(){
+        let (sender, receiver) = bounded::<u32, 3, true, true>(1);
+        for val in (0..10_000).chain(usize::MAX - 10..=usize::MAX){
+            receiver.switch_add(val);
+            receiver.switch_sub(val);
+            receiver.switch_and(val);
+            receiver.switch_max(val);
+            receiver.switch_min(val);
+            receiver.switch_nand(val);
+            receiver.switch_or(val);
+            receiver.switch_xor(val);
+            sender.switch_add(val);
+            sender.switch_sub(val);
+            sender.switch_nand(val);
+            sender.switch_max(val);
+        }
+    }
+}
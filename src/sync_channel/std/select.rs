@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+use event_listener::EventListener;
+use crate::err::lane::OutOfRangeError;
+use crate::ready::Ready;
+use super::SwitchReceiver;
+use super::lane::Lane;
+use super::wait::wait_any;
+
+impl<T> Ready for Lane<T>{
+    fn is_ready(&self) -> bool{
+        Lane::is_ready(self)
+    }
+
+    fn listen(&self, listeners: &mut Vec<EventListener>){
+        listeners.push(self.listen_ready());
+    }
+}
+
+impl<T, const N: usize, const P: bool> Ready for SwitchReceiver<T, N, P>{
+    fn is_ready(&self) -> bool{
+        self.lanes[self.count.lane(N)].is_ready()
+    }
+
+    fn listen(&self, listeners: &mut Vec<EventListener>){
+        // The active lane can change while waiting, so listen to all of them, and to the switch itself.
+        for lane in self.lanes.iter(){
+            listeners.push(lane.listen_ready());
+        }
+        listeners.push(self.count.listen());
+    }
+}
+
+/// Waits until one of several switch receivers, or specific lanes of them, is ready.
+///
+/// A receive is ready when it wouldn't block, because there is a message or the lane is closed.
+/// Like `crossbeam_channel::Select`, nothing is received, so another handle can still take the message
+/// before the ready receiver gets to it.
+///
+/// A receiver that follows the active lane is checked again whenever one of its lanes gets a message
+/// or the channel switches lanes.
+pub struct Select<'a>{
+    ops: Vec<Box<dyn Ready + 'a>>,
+    // Where the next check starts, so a busy receiver can't starve the others.
+    next: usize,
+}
+
+impl<'a> Select<'a>{
+    pub fn new() -> Self{
+        Self{
+            ops: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// Add a receive from whichever lane of `receiver` is active, returning the index of the operation.
+    pub fn recv<T, const N: usize, const P: bool>(&mut self, receiver: &'a SwitchReceiver<T, N, P>) -> usize{
+        self.ops.push(Box::new(receiver));
+        self.ops.len() - 1
+    }
+
+    /// Add a receive from `lane` of `receiver`, returning the index of the operation.
+    pub fn recv_from<T, const N: usize, const P: bool>(&mut self, receiver: &'a SwitchReceiver<T, N, P>, lane: usize) -> Result<usize, OutOfRangeError>{
+        let lane = receiver.lanes.get(lane).ok_or(OutOfRangeError{ lane, lanes: N })?;
+        self.ops.push(Box::new(lane));
+        Ok(self.ops.len() - 1)
+    }
+
+    /// Get the index of a ready operation, if there is one.
+    pub fn try_ready(&mut self) -> Option<usize>{
+        let count = self.ops.len();
+        let index = (0..count)
+            .map(|offset| (self.next + offset) % count)
+            .find(|&index| self.ops[index].is_ready())?;
+        self.next = (index + 1) % count;
+        Some(index)
+    }
+
+    /// Block until an operation is ready, and get its index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no operations have been added.
+    pub fn ready(&mut self) -> usize{
+        self.ready_until(None).unwrap()
+    }
+
+    /// Block until an operation is ready, giving up after `timeout`.
+    pub fn ready_timeout(&mut self, timeout: Duration) -> Option<usize>{
+        // A timeout too far in the future is the same as no timeout at all.
+        self.ready_until(Instant::now().checked_add(timeout))
+    }
+
+    /// Block until an operation is ready, giving up at `deadline`.
+    pub fn ready_deadline(&mut self, deadline: Instant) -> Option<usize>{
+        self.ready_until(Some(deadline))
+    }
+
+    fn ready_until(&mut self, deadline: Option<Instant>) -> Option<usize>{
+        assert!(!self.ops.is_empty(), "no operations have been added to `Select`");
+        loop{
+            // Start listening before checking, so nothing that happens in between is missed.
+            let mut listeners = Vec::new();
+            for op in self.ops.iter(){
+                op.listen(&mut listeners);
+            }
+            if let Some(index) = self.try_ready(){
+                return Some(index);
+            }
+            if !wait_any(&mut listeners, deadline){
+                return self.try_ready();
+            }
+        }
+    }
+}
+
+impl<'a> Default for Select<'a>{
+    fn default() -> Self{
+        Self::new()
+    }
+}
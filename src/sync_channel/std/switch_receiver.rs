@@ -1,148 +1,594 @@
-use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
-use std::sync::mpsc::Receiver;
-use crate::{PERMITTED};
-use crate::{Switcher, err::recv::{RecvError, TryRecvError}};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::{Lanes, PERMITTED, Position, Rotation, SwitchCounter, SwitchOp, SwitchOutcome, SwitchState, switch_lane, update_lane};
+use crate::{Switcher, err::lane::OutOfRangeError, err::recv::{RecvError, RecvTimeoutError, TryRecvError}};
+use super::lane::{Handles, Lane, OwnedLanes, close_lanes};
+use crate::rotation::Rotator;
+use super::wait::{wait_any, wait_for_epoch};
 
 pub struct SwitchReceiver<T, const N: usize, const P: bool>{
-    pub(crate) count: Arc<AtomicUsize>,
-    pub(crate) receivers: [Receiver<T>; N],
+    pub(crate) count: Arc<SwitchCounter>,
+    pub(crate) lanes: Arc<[Lane<T>; N]>,
+    pub(crate) handles: Arc<Handles>,
+    pub(crate) rotation: Option<Rotator>,
 }
 
 impl<T, const N: usize, const P: bool> SwitchReceiver<T, N, P>{
+    fn active(&self) -> &Lane<T>{
+        &self.lanes[self.count.lane(N)]
+    }
+
     /// Try to receive from the activate channel.
+    ///
+    /// With a rotation set, this moves on past empty lanes, so it only fails if every lane is empty.
     pub fn try_recv(&self) -> Result<T, TryRecvError>{
-        Ok(self.receivers[self.count.load(Ordering::SeqCst) % N].try_recv()?)
+        match &self.rotation{
+            Some(rotator) => rotator.try_recv(&self.count, N, |lane| self.lanes[lane].try_recv()),
+            None => self.active().try_recv(),
+        }
     }
 
     /// receive from the activate channel.
+    ///
+    /// With a rotation set, this waits for a message on any lane.
     pub fn recv(&self) -> Result<T, RecvError>{
-        Ok(self.receivers[self.count.load(Ordering::SeqCst) % N].recv()?)
+        match self.rotation{
+            Some(_) => self.rotating_recv(None).map_err(|_| RecvError),
+            None => self.active().recv(),
+        }
+    }
+
+    /// Receive from the activate channel, waiting at most `timeout` for a message.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError>{
+        match Instant::now().checked_add(timeout){
+            Some(deadline) => self.recv_deadline(deadline),
+            // Too far in the future to ever time out.
+            None => Ok(self.recv()?),
+        }
+    }
+
+    /// Receive from the activate channel, waiting until `deadline` at the latest for a message.
+    pub fn recv_deadline(&self, deadline: Instant) -> Result<T, RecvTimeoutError>{
+        match self.rotation{
+            Some(_) => self.rotating_recv(Some(deadline)),
+            None => self.active().recv_deadline(deadline),
+        }
+    }
+
+    fn rotating_recv(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError>{
+        loop{
+            // Start listening before trying, so a message sent in between isn't missed.
+            let mut listeners: Vec<_> = self.lanes.iter().map(|lane| lane.listen_ready()).collect();
+            match self.try_recv(){
+                Ok(msg) => return Ok(msg),
+                Err(TryRecvError::Closed) => return Err(RecvTimeoutError::Closed),
+                Err(TryRecvError::Empty) => if !wait_any(&mut listeners, deadline){
+                    return Err(RecvTimeoutError::Timeout);
+                },
+            }
+        }
+    }
+
+    /// Close all the channels.
+    pub fn close(&self) -> bool{
+        close_lanes(&self.lanes[..])
+    }
+
+    /// Checks if all the channels have been closed.
+    pub fn is_closed(&self) -> bool{
+        self.lanes[0].is_closed()
+    }
+
+    /// Check if the activate channel is empty.
+    pub fn is_empty(&self) -> bool{
+        self.active().is_empty()
+    }
+
+    /// Check if the activate channel is full, unbounded channels are never full.
+    pub fn is_full(&self) -> bool{
+        self.active().is_full()
+    }
+
+    /// Returns the number of messages in the activate channel.
+    pub fn len(&self) -> usize{
+        self.active().len()
+    }
+
+    /// Returns the capacity of the active lane, `None` if it's unbounded.
+    pub fn capacity(&self) -> Option<usize>{
+        self.active().capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.handles.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.handles.receiver_count()
+    }
+
+    /// Returns the epoch the channel is in, which moves on every time the channel switches to a different lane.
+    pub fn epoch(&self) -> usize{
+        self.count.epoch()
     }
 
     pub fn iter(&self) -> SwitchReceiverGuardIterator<'_, T>{
         SwitchReceiverGuardIterator{
-            receiver: &self.receivers[self.count.load(Ordering::SeqCst) % N]
+            lane: self.active()
         }
     }
 
     pub fn try_iter(&self) -> SwitchReceiverGuardTryIterator<'_, T>{
         SwitchReceiverGuardTryIterator{
-            receiver: &self.receivers[self.count.load(Ordering::SeqCst) % N]
+            lane: self.active()
         }
     }
 
     pub fn get_guard(&self) -> SwitchReceiverGuard<'_, T>{
+        self.guard(self.count.state(N))
+    }
+
+    /// Block until another handle switches the channel to a different lane, returning a guard for the new lane.
+    ///
+    /// Only switches after the call are waited for, a guard's `wait_for_switch` also catches
+    /// the ones since the guard was taken.
+    pub fn wait_for_switch(&self) -> SwitchReceiverGuard<'_, T>{
+        self.get_guard().wait_for_switch();
+        self.get_guard()
+    }
+
+    /// Like `wait_for_switch`, but gives up after `timeout`.
+    pub fn wait_for_switch_timeout(&self, timeout: Duration) -> Option<SwitchReceiverGuard<'_, T>>{
+        match Instant::now().checked_add(timeout){
+            Some(deadline) => self.wait_for_switch_deadline(deadline),
+            // Too far in the future to ever time out.
+            None => Some(self.wait_for_switch()),
+        }
+    }
+
+    /// Like `wait_for_switch`, but gives up at `deadline`.
+    pub fn wait_for_switch_deadline(&self, deadline: Instant) -> Option<SwitchReceiverGuard<'_, T>>{
+        if self.get_guard().wait_for_switch_deadline(deadline){
+            Some(self.get_guard())
+        }else{
+            None
+        }
+    }
+
+    /// Get a guard for `lane` without switching the channel.
+    pub fn guard_for(&self, lane: usize) -> Result<SwitchReceiverGuard<'_, T>, OutOfRangeError>{
+        if lane < N{
+            Ok(self.guard(SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: N })
+        }
+    }
+
+    /// The same as `guard_for`, so a specific lane can be used as `receiver.recv_from(lane)?.recv()`.
+    pub fn recv_from(&self, lane: usize) -> Result<SwitchReceiverGuard<'_, T>, OutOfRangeError>{
+        self.guard_for(lane)
+    }
+
+    fn guard(&self, state: SwitchState) -> SwitchReceiverGuard<'_, T>{
         SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.load(Ordering::SeqCst) % N]
+            lane: &self.lanes[state.lane],
+            handles: &self.handles,
+            position: Position{ count: &self.count, state, lanes: Lanes::Fixed(N) },
+        }
+    }
+
+    /// Like `get_guard`, but the guard keeps its own handle on the channel, so it can be moved into another thread.
+    pub fn get_owned_guard(&self) -> OwnedSwitchReceiverGuard<T>{
+        self.owned_guard(self.count.state(N))
+    }
+
+    /// Like `guard_for`, but the guard keeps its own handle on the channel, so it can be moved into another thread.
+    pub fn owned_guard_for(&self, lane: usize) -> Result<OwnedSwitchReceiverGuard<T>, OutOfRangeError>{
+        if lane < N{
+            Ok(self.owned_guard(SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: N })
+        }
+    }
+
+    fn owned_guard(&self, state: SwitchState) -> OwnedSwitchReceiverGuard<T>{
+        self.handles.add_receiver();
+        OwnedSwitchReceiverGuard{
+            lanes: OwnedLanes::Fixed(self.lanes.clone()),
+            state,
+            handles: self.handles.clone(),
+            count: self.count.clone(),
         }
     }
 } 
 
-#[derive(Clone)]
+impl<T, const N: usize> SwitchReceiver<T, N, PERMITTED>{
+    /// Let the receiver switch lanes by itself according to `rotation`, whenever it receives from the active lane.
+    ///
+    /// Panics if `rotation` is `Weighted` with a different number of weights than lanes, or a weight of 0.
+    /// Only the receiver's own `try_recv`, `recv`, `recv_timeout` and `recv_deadline` rotate,
+    /// guards and iterators don't. Clones rotate the same way, but count their messages separately.
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self{
+        self.rotation = Some(Rotator::new(rotation, N));
+        self
+    }
+
+    /// Like `switch_outcome`, but with owned guards.
+    pub fn switch_outcome_owned(&self, op: SwitchOp) -> SwitchOutcome<OwnedSwitchReceiverGuard<T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state| self.owned_guard(state))
+    }
+
+    /// Like `switch_update`, but with owned guards.
+    pub fn switch_update_owned<F: FnMut(usize) -> Option<usize>>(&self, f: F) -> Result<OwnedSwitchReceiverGuard<T>, OwnedSwitchReceiverGuard<T>>{
+        let guard = |state: SwitchState| self.owned_guard(state);
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
+
+impl<T, const N: usize, const P: bool> Clone for SwitchReceiver<T, N, P>{
+    fn clone(&self) -> Self{
+        self.handles.add_receiver();
+        Self{
+            count: self.count.clone(),
+            lanes: self.lanes.clone(),
+            handles: self.handles.clone(),
+            rotation: self.rotation.clone(),
+        }
+    }
+}
+
+impl<T, const N: usize, const P: bool> Drop for SwitchReceiver<T, N, P>{
+    fn drop(&mut self){
+        if self.handles.remove_receiver(){
+            self.close();
+        }
+    }
+}
+
 pub struct SwitchReceiverGuard<'a, T>{
-    receiver: &'a Receiver<T>
+    pub(crate) lane: &'a Lane<T>,
+    pub(crate) handles: &'a Handles,
+    pub(crate) position: Position<'a>,
+}
+
+impl<'a, T> Clone for SwitchReceiverGuard<'a, T>{
+    fn clone(&self) -> Self{
+        Self{
+            lane: self.lane,
+            handles: self.handles,
+            position: self.position,
+        }
+    }
 }
 
 impl<'a, T> SwitchReceiverGuard<'a, T>{
     /// Try to receive from the activate channel.
     pub fn try_recv(&self) -> Result<T, TryRecvError>{
-        Ok(self.receiver.try_recv()?)
+        self.lane.try_recv()
     }
 
     /// receive from the activate channel.
     pub fn recv(&'_ self) -> Result<T, RecvError>{
-        Ok(self.receiver.recv()?)
+        self.lane.recv()
+    }
+
+    /// Receive from the activate channel, waiting at most `timeout` for a message.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError>{
+        self.lane.recv_timeout(timeout)
+    }
+
+    /// Receive from the activate channel, waiting until `deadline` at the latest for a message.
+    pub fn recv_deadline(&self, deadline: Instant) -> Result<T, RecvTimeoutError>{
+        self.lane.recv_deadline(deadline)
+    }
+
+    /// Checks if all the channels have been closed.
+    pub fn is_closed(&self) -> bool{
+        self.lane.is_closed()
+    }
+
+    /// Check if the activate channel is empty.
+    pub fn is_empty(&self) -> bool{
+        self.lane.is_empty()
+    }
+
+    /// Check if the activate channel is full, unbounded channels are never full.
+    pub fn is_full(&self) -> bool{
+        self.lane.is_full()
+    }
+
+    /// Returns the number of messages in the activate channel.
+    pub fn len(&self) -> usize{
+        self.lane.len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.lane.capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.handles.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.handles.receiver_count()
     }
 
     pub fn iter(&self) -> SwitchReceiverGuardIterator<'_, T>{
         SwitchReceiverGuardIterator{
-            receiver: &self.receiver
+            lane: self.lane
         }
     }
 
     pub fn try_iter(&self) -> SwitchReceiverGuardTryIterator<'_, T>{
         SwitchReceiverGuardTryIterator{
-            receiver: &self.receiver
+            lane: self.lane
         }
     }
-} 
-
-pub struct SwitchReceiverGuardIterator<'a, T>{
-    receiver: &'a Receiver<T>,
-}
 
-impl<'a, T> std::iter::Iterator for SwitchReceiverGuardIterator<'a, T>{
+    /// Check if the guard's lane is still the active lane of the channel.
+    pub fn is_active(&self) -> bool{
+        self.position.is_active()
+    }
 
-    type Item = T;
-    fn next(&mut self) -> std::option::Option<<Self as std::iter::Iterator>::Item> { 
-        self.receiver.recv().ok()
+    /// The epoch the channel was in when the guard was taken.
+    pub fn epoch(&self) -> usize{
+        self.position.state.epoch
     }
-}
 
-pub struct SwitchReceiverGuardTryIterator<'a, T>{
-    receiver: &'a Receiver<T>,
-}
+    /// Check if the channel hasn't switched lanes since the guard was taken.
+    ///
+    /// Unlike `is_active`, this is `false` once the channel has switched away, even if it has switched back since.
+    pub fn is_current(&self) -> bool{
+        self.position.is_current()
+    }
 
-impl<'a, T> std::iter::Iterator for SwitchReceiverGuardTryIterator<'a, T>{
+    /// Block until the channel has switched lanes since the guard was taken, returning straight away if it already has.
+    pub fn wait_for_switch(&self){
+        wait_for_epoch(self.position.count, self.position.state.epoch, None);
+    }
 
-    type Item = T;
-    fn next(&mut self) -> std::option::Option<<Self as std::iter::Iterator>::Item> { 
-        self.receiver.try_recv().ok()
+    /// Like `wait_for_switch`, but gives up after `timeout`, returning `false` if the channel still hasn't switched.
+    pub fn wait_for_switch_timeout(&self, timeout: Duration) -> bool{
+        match Instant::now().checked_add(timeout){
+            Some(deadline) => self.wait_for_switch_deadline(deadline),
+            // Too far in the future to ever time out.
+            None => wait_for_epoch(self.position.count, self.position.state.epoch, None),
+        }
     }
-}
 
-impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchReceiver<T, N, PERMITTED>{
-    type Output = SwitchReceiverGuard<'a, T>;
+    /// Like `wait_for_switch`, but gives up at `deadline`, returning `false` if the channel still hasn't switched.
+    pub fn wait_for_switch_deadline(&self, deadline: Instant) -> bool{
+        wait_for_epoch(self.position.count, self.position.state.epoch, Some(deadline))
+    }
 
-    fn switch_add(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_add(val, Ordering::SeqCst)]
+    /// Receive from the guard's lane until it's empty and no longer the active lane.
+    ///
+    /// While the lane is still active the iterator blocks for more messages,
+    /// so messages sent just before a switch aren't left behind.
+    /// Once the channel has switched away, it ends as soon as the lane is empty.
+    pub fn drain(&self) -> SwitchReceiverGuardDrain<'a, T>{
+        SwitchReceiverGuardDrain{
+            lane: self.lane,
+            position: self.position,
         }
     }
+} 
+
+/// A `SwitchReceiverGuard` that isn't tied to the lifetime of its `SwitchReceiver`.
+///
+/// It counts as a receiver of the channel until it's dropped.
+pub struct OwnedSwitchReceiverGuard<T>{
+    pub(crate) lanes: OwnedLanes<T>,
+    pub(crate) state: SwitchState,
+    pub(crate) handles: Arc<Handles>,
+    pub(crate) count: Arc<SwitchCounter>,
+}
 
-    fn switch_and(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
+impl<T> OwnedSwitchReceiverGuard<T>{
+    /// Borrow the guard as a `SwitchReceiverGuard`.
+    pub fn guard(&self) -> SwitchReceiverGuard<'_, T>{
         SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_and(val, Ordering::SeqCst)]
+            lane: self.lanes.get(self.state.lane),
+            handles: &self.handles,
+            position: Position{ count: &self.count, state: self.state, lanes: self.lanes.lanes() },
         }
     }
 
-    fn switch_max(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_max(val, Ordering::SeqCst)]
-        }
+    /// The lane this guard receives from.
+    pub fn lane(&self) -> usize{
+        self.state.lane
     }
 
-    fn switch_min(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_min(val, Ordering::SeqCst)]
+    /// The epoch the channel was in when the guard was taken.
+    pub fn epoch(&self) -> usize{
+        self.state.epoch
+    }
+
+    /// Try to receive from the activate channel.
+    pub fn try_recv(&self) -> Result<T, TryRecvError>{
+        self.guard().try_recv()
+    }
+
+    /// receive from the activate channel.
+    pub fn recv(&self) -> Result<T, RecvError>{
+        self.guard().recv()
+    }
+
+    /// Receive from the activate channel, waiting at most `timeout` for a message.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError>{
+        self.guard().recv_timeout(timeout)
+    }
+
+    /// Receive from the activate channel, waiting until `deadline` at the latest for a message.
+    pub fn recv_deadline(&self, deadline: Instant) -> Result<T, RecvTimeoutError>{
+        self.guard().recv_deadline(deadline)
+    }
+
+    /// Checks if all the channels have been closed.
+    pub fn is_closed(&self) -> bool{
+        self.guard().is_closed()
+    }
+
+    /// Check if the activate channel is empty.
+    pub fn is_empty(&self) -> bool{
+        self.guard().is_empty()
+    }
+
+    /// Check if the activate channel is full, unbounded channels are never full.
+    pub fn is_full(&self) -> bool{
+        self.guard().is_full()
+    }
+
+    /// Returns the number of messages in the activate channel.
+    pub fn len(&self) -> usize{
+        self.guard().len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.guard().capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.handles.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.handles.receiver_count()
+    }
+
+    pub fn iter(&self) -> SwitchReceiverGuardIterator<'_, T>{
+        SwitchReceiverGuardIterator{
+            lane: self.lanes.get(self.state.lane)
         }
     }
 
-    fn switch_nand(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_nand(val, Ordering::SeqCst)]
+    pub fn try_iter(&self) -> SwitchReceiverGuardTryIterator<'_, T>{
+        SwitchReceiverGuardTryIterator{
+            lane: self.lanes.get(self.state.lane)
         }
     }
 
-    fn switch_or(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_or(val, Ordering::SeqCst)]
+    /// Check if the guard's lane is still the active lane of the channel.
+    pub fn is_active(&self) -> bool{
+        self.guard().is_active()
+    }
+
+    /// Check if the channel hasn't switched lanes since the guard was taken.
+    pub fn is_current(&self) -> bool{
+        self.guard().is_current()
+    }
+
+    /// Block until the channel has switched lanes since the guard was taken, like `SwitchReceiverGuard::wait_for_switch`.
+    pub fn wait_for_switch(&self){
+        self.guard().wait_for_switch()
+    }
+
+    /// Like `wait_for_switch`, but gives up after `timeout`, returning `false` if the channel still hasn't switched.
+    pub fn wait_for_switch_timeout(&self, timeout: Duration) -> bool{
+        self.guard().wait_for_switch_timeout(timeout)
+    }
+
+    /// Like `wait_for_switch`, but gives up at `deadline`, returning `false` if the channel still hasn't switched.
+    pub fn wait_for_switch_deadline(&self, deadline: Instant) -> bool{
+        self.guard().wait_for_switch_deadline(deadline)
+    }
+
+    /// Receive from the guard's lane until it's empty and no longer the active lane, like `SwitchReceiverGuard::drain`.
+    pub fn drain(&self) -> SwitchReceiverGuardDrain<'_, T>{
+        self.guard().drain()
+    }
+}
+
+impl<T> Clone for OwnedSwitchReceiverGuard<T>{
+    fn clone(&self) -> Self{
+        self.handles.add_receiver();
+        Self{
+            lanes: self.lanes.clone(),
+            state: self.state,
+            handles: self.handles.clone(),
+            count: self.count.clone(),
         }
     }
+}
 
-    fn switch_sub(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_sub(val, Ordering::SeqCst)]
+impl<T> Drop for OwnedSwitchReceiverGuard<T>{
+    fn drop(&mut self){
+        if self.handles.remove_receiver(){
+            self.lanes.close();
         }
     }
+}
 
-    fn switch_xor(&self, val: usize) -> SwitchReceiverGuard<'_, T>{
-        SwitchReceiverGuard{
-            receiver: &self.receivers[self.count.fetch_xor(val, Ordering::SeqCst)]
+pub struct SwitchReceiverGuardIterator<'a, T>{
+    pub(crate) lane: &'a Lane<T>,
+}
+
+impl<'a, T> std::iter::Iterator for SwitchReceiverGuardIterator<'a, T>{
+
+    type Item = T;
+    fn next(&mut self) -> std::option::Option<<Self as std::iter::Iterator>::Item> { 
+        self.lane.recv().ok()
+    }
+}
+
+pub struct SwitchReceiverGuardTryIterator<'a, T>{
+    pub(crate) lane: &'a Lane<T>,
+}
+
+impl<'a, T> std::iter::Iterator for SwitchReceiverGuardTryIterator<'a, T>{
+
+    type Item = T;
+    fn next(&mut self) -> std::option::Option<<Self as std::iter::Iterator>::Item> { 
+        self.lane.try_recv().ok()
+    }
+}
+
+pub struct SwitchReceiverGuardDrain<'a, T>{
+    lane: &'a Lane<T>,
+    position: Position<'a>,
+}
+
+impl<'a, T> std::iter::Iterator for SwitchReceiverGuardDrain<'a, T>{
+
+    type Item = T;
+    fn next(&mut self) -> std::option::Option<<Self as std::iter::Iterator>::Item> { 
+        loop{
+            // Start listening before checking, so a message or a switch in between isn't missed.
+            let mut listeners = [self.lane.listen_sent(), self.position.count.listen()];
+            // Check for the switch before trying, so a message sent just before it is still received.
+            let active = self.position.is_active();
+            match self.lane.try_recv(){
+                Ok(msg) => return Some(msg),
+                Err(TryRecvError::Closed) => return None,
+                Err(TryRecvError::Empty) => if !active{
+                    return None;
+                },
+            }
+            wait_any(&mut listeners, None);
         }
     }
 }
 
+impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchReceiver<T, N, PERMITTED>{
+    type Output = SwitchReceiverGuard<'a, T>;
+
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<SwitchReceiverGuard<'a, T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state| self.guard(state))
+    }
+
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<SwitchReceiverGuard<'a, T>, SwitchReceiverGuard<'a, T>>{
+        let guard = |state: SwitchState| self.guard(state);
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
+
 impl From<std::sync::mpsc::TryRecvError> for TryRecvError{
     fn from(err: std::sync::mpsc::TryRecvError) -> Self { 
         match err{
@@ -156,4 +602,4 @@ impl From<std::sync::mpsc::RecvError> for RecvError{
     fn from(_: std::sync::mpsc::RecvError) -> Self { 
         Self
     }
-}
\ No newline at end of file
+}
@@ -1,79 +1,330 @@
-use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
-use std::sync::mpsc::Sender;
-use crate::{PERMITTED};
-use crate::{Switcher, err::send::{SendError, TrySendError}};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::{Lanes, PERMITTED, Position, SwitchCounter, SwitchOp, SwitchOutcome, SwitchState, switch_lane, update_lane};
+use crate::{Switcher, err::lane::OutOfRangeError, err::send::SendError};
+use super::lane::{Handles, Lane, OwnedLanes, close_lanes};
+use super::wait::wait_for_epoch;
 
-#[derive(Clone)]
 pub struct SwitchSender<T, const N: usize, const P: bool>{
-    pub(crate) count: Arc<AtomicUsize>,
-    pub(crate) senders: [Sender<T>; N],
+    pub(crate) count: Arc<SwitchCounter>,
+    pub(crate) lanes: Arc<[Lane<T>; N]>,
+    pub(crate) handles: Arc<Handles>,
 }
 
 impl<T, const N: usize, const P: bool> SwitchSender<T, N, P>{
+    fn active(&self) -> &Lane<T>{
+        &self.lanes[self.count.lane(N)]
+    }
+
     pub fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
-        Ok(self.senders[self.count.load(Ordering::SeqCst) % N].send(msg)?)
+        self.active().send(msg)
     }
-}
 
-#[derive(Clone)]
-pub struct SwitchSenderGuard<'a, T>{
-    sender: &'a Sender<T>
-}
+    /// Close all the channels.
+    pub fn close(&self) -> bool{
+        close_lanes(&self.lanes[..])
+    }
 
-impl<'a, T> SwitchSenderGuard<'a, T>{
-    pub fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
-        Ok(self.sender.send(msg)?)
+    pub fn is_closed(&self) -> bool{
+        self.lanes[0].is_closed()
     }
-}
 
-impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchSender<T, N, PERMITTED>{
-    type Output = SwitchSenderGuard<'a, T>;
+    pub fn is_empty(&self) -> bool{
+        self.active().is_empty()
+    }
 
-    fn switch_add(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_add(val, Ordering::SeqCst)]
+    pub fn is_full(&self) -> bool{
+        self.active().is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.active().len()
+    }
+
+    /// Returns the capacity of the active lane, `None` if it's unbounded.
+    pub fn capacity(&self) -> Option<usize>{
+        self.active().capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.handles.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.handles.receiver_count()
+    }
+
+    /// Returns the epoch the channel is in, which moves on every time the channel switches to a different lane.
+    pub fn epoch(&self) -> usize{
+        self.count.epoch()
+    }
+
+    /// Get a guard for `lane` without switching the channel.
+    pub fn guard_for(&self, lane: usize) -> Result<SwitchSenderGuard<'_, T>, OutOfRangeError>{
+        if lane < N{
+            Ok(self.guard(SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: N })
         }
     }
 
-    fn switch_and(&self, val: usize) -> SwitchSenderGuard<'_, T>{
+    /// The same as `guard_for`, so a specific lane can be used as `sender.send_to(lane)?.send(msg)`.
+    pub fn send_to(&self, lane: usize) -> Result<SwitchSenderGuard<'_, T>, OutOfRangeError>{
+        self.guard_for(lane)
+    }
+
+    fn guard(&self, state: SwitchState) -> SwitchSenderGuard<'_, T>{
         SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_and(val, Ordering::SeqCst)]
+            lane: &self.lanes[state.lane],
+            handles: &self.handles,
+            position: Position{ count: &self.count, state, lanes: Lanes::Fixed(N) },
         }
     }
 
-    fn switch_max(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_max(val, Ordering::SeqCst)]
+    /// Block until another handle switches the channel to a different lane, returning a guard for the new lane.
+    pub fn wait_for_switch(&self) -> SwitchSenderGuard<'_, T>{
+        wait_for_epoch(&self.count, self.count.epoch(), None);
+        self.guard(self.count.state(N))
+    }
+
+    /// Like `wait_for_switch`, but gives up after `timeout`.
+    pub fn wait_for_switch_timeout(&self, timeout: Duration) -> Option<SwitchSenderGuard<'_, T>>{
+        match Instant::now().checked_add(timeout){
+            Some(deadline) => self.wait_for_switch_deadline(deadline),
+            // Too far in the future to ever time out.
+            None => Some(self.wait_for_switch()),
         }
     }
 
-    fn switch_min(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_min(val, Ordering::SeqCst)]
+    /// Like `wait_for_switch`, but gives up at `deadline`.
+    pub fn wait_for_switch_deadline(&self, deadline: Instant) -> Option<SwitchSenderGuard<'_, T>>{
+        if wait_for_epoch(&self.count, self.count.epoch(), Some(deadline)){
+            Some(self.guard(self.count.state(N)))
+        }else{
+            None
         }
     }
 
-    fn switch_nand(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_nand(val, Ordering::SeqCst)]
+    /// Get a guard for the active lane that keeps its own handle on the channel, so it can be moved into another thread.
+    pub fn get_owned_guard(&self) -> OwnedSwitchSenderGuard<T>{
+        self.owned_guard(self.count.state(N))
+    }
+
+    /// Like `guard_for`, but the guard keeps its own handle on the channel, so it can be moved into another thread.
+    pub fn owned_guard_for(&self, lane: usize) -> Result<OwnedSwitchSenderGuard<T>, OutOfRangeError>{
+        if lane < N{
+            Ok(self.owned_guard(SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: N })
         }
     }
 
-    fn switch_or(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_or(val, Ordering::SeqCst)]
+    fn owned_guard(&self, state: SwitchState) -> OwnedSwitchSenderGuard<T>{
+        self.handles.add_sender();
+        OwnedSwitchSenderGuard{
+            lanes: OwnedLanes::Fixed(self.lanes.clone()),
+            state,
+            handles: self.handles.clone(),
+            count: self.count.clone(),
         }
     }
+}
 
-    fn switch_sub(&self, val: usize) -> SwitchSenderGuard<'_, T>{
-        SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_sub(val, Ordering::SeqCst)]
+impl<T, const N: usize> SwitchSender<T, N, PERMITTED>{
+    /// Like `switch_outcome`, but with owned guards.
+    pub fn switch_outcome_owned(&self, op: SwitchOp) -> SwitchOutcome<OwnedSwitchSenderGuard<T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state| self.owned_guard(state))
+    }
+
+    /// Like `switch_update`, but with owned guards.
+    pub fn switch_update_owned<F: FnMut(usize) -> Option<usize>>(&self, f: F) -> Result<OwnedSwitchSenderGuard<T>, OwnedSwitchSenderGuard<T>>{
+        let guard = |state: SwitchState| self.owned_guard(state);
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
+
+impl<T, const N: usize, const P: bool> Clone for SwitchSender<T, N, P>{
+    fn clone(&self) -> Self{
+        self.handles.add_sender();
+        Self{
+            count: self.count.clone(),
+            lanes: self.lanes.clone(),
+            handles: self.handles.clone(),
         }
     }
+}
+
+impl<T, const N: usize, const P: bool> Drop for SwitchSender<T, N, P>{
+    fn drop(&mut self){
+        if self.handles.remove_sender(){
+            self.close();
+        }
+    }
+}
+
+pub struct SwitchSenderGuard<'a, T>{
+    lane: &'a Lane<T>,
+    handles: &'a Handles,
+    position: Position<'a>,
+}
+
+impl<'a, T> Clone for SwitchSenderGuard<'a, T>{
+    fn clone(&self) -> Self{
+        Self{
+            lane: self.lane,
+            handles: self.handles,
+            position: self.position,
+        }
+    }
+}
+
+impl<'a, T> SwitchSenderGuard<'a, T>{
+    pub fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
+        self.lane.send(msg)
+    }
+
+    pub fn is_closed(&self) -> bool{
+        self.lane.is_closed()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.lane.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.lane.is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.lane.len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.lane.capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.handles.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.handles.receiver_count()
+    }
+
+    /// The epoch the channel was in when the guard was taken.
+    pub fn epoch(&self) -> usize{
+        self.position.state.epoch
+    }
 
-    fn switch_xor(&self, val: usize) -> SwitchSenderGuard<'_, T>{
+    /// Check if the channel hasn't switched lanes since the guard was taken.
+    pub fn is_current(&self) -> bool{
+        self.position.is_current()
+    }
+}
+
+/// A `SwitchSenderGuard` that isn't tied to the lifetime of its `SwitchSender`.
+///
+/// It counts as a sender of the channel until it's dropped.
+pub struct OwnedSwitchSenderGuard<T>{
+    lanes: OwnedLanes<T>,
+    state: SwitchState,
+    handles: Arc<Handles>,
+    count: Arc<SwitchCounter>,
+}
+
+impl<T> OwnedSwitchSenderGuard<T>{
+    /// Borrow the guard as a `SwitchSenderGuard`.
+    pub fn guard(&self) -> SwitchSenderGuard<'_, T>{
         SwitchSenderGuard{
-            sender: &self.senders[self.count.fetch_xor(val, Ordering::SeqCst)]
+            lane: self.lanes.get(self.state.lane),
+            handles: &self.handles,
+            position: Position{ count: &self.count, state: self.state, lanes: self.lanes.lanes() },
         }
     }
+
+    /// The lane this guard sends into.
+    pub fn lane(&self) -> usize{
+        self.state.lane
+    }
+
+    /// The epoch the channel was in when the guard was taken.
+    pub fn epoch(&self) -> usize{
+        self.state.epoch
+    }
+
+    /// Check if the channel hasn't switched lanes since the guard was taken.
+    pub fn is_current(&self) -> bool{
+        self.guard().is_current()
+    }
+
+    pub fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
+        self.guard().send(msg)
+    }
+
+    pub fn is_closed(&self) -> bool{
+        self.guard().is_closed()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.guard().is_empty()
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.guard().is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.guard().len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.guard().capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.handles.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.handles.receiver_count()
+    }
+}
+
+impl<T> Clone for OwnedSwitchSenderGuard<T>{
+    fn clone(&self) -> Self{
+        self.handles.add_sender();
+        Self{
+            lanes: self.lanes.clone(),
+            state: self.state,
+            handles: self.handles.clone(),
+            count: self.count.clone(),
+        }
+    }
+}
+
+impl<T> Drop for OwnedSwitchSenderGuard<T>{
+    fn drop(&mut self){
+        if self.handles.remove_sender(){
+            self.lanes.close();
+        }
+    }
+}
+
+impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchSender<T, N, PERMITTED>{
+    type Output = SwitchSenderGuard<'a, T>;
+
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<SwitchSenderGuard<'a, T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state| self.guard(state))
+    }
+
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<SwitchSenderGuard<'a, T>, SwitchSenderGuard<'a, T>>{
+        let guard = |state: SwitchState| self.guard(state);
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
 }
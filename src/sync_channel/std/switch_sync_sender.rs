@@ -1,87 +1,372 @@
-use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
-use std::sync::mpsc::SyncSender;
-use crate::{PERMITTED};
-use crate::{Switcher, err::send::{SendError, TrySendError}};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::{Lanes, PERMITTED, Position, SwitchCounter, SwitchOp, SwitchOutcome, SwitchState, switch_lane, update_lane};
+use crate::{Switcher, err::lane::OutOfRangeError, err::send::{SendError, SendTimeoutError, TrySendError}};
+use super::lane::{Handles, Lane, OwnedLanes, close_lanes};
+use super::wait::wait_for_epoch;
 
-#[derive(Clone)]
 pub struct SwitchSyncSender<T, const N: usize, const P: bool>{
-    pub(crate) count: Arc<AtomicUsize>,
-    pub(crate) senders: [SyncSender<T>; N],
+    pub(crate) count: Arc<SwitchCounter>,
+    pub(crate) lanes: Arc<[Lane<T>; N]>,
+    pub(crate) handles: Arc<Handles>,
 }
 
 impl<T, const N: usize, const P: bool> SwitchSyncSender<T, N, P>{
+    fn active(&self) -> &Lane<T>{
+        &self.lanes[self.count.lane(N)]
+    }
+
     pub fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
-        Ok(self.senders[self.count.load(Ordering::SeqCst) % N].send(msg)?)
+        self.active().send(msg)
     }
 
     pub fn try_send(&'_ self, msg: T) -> Result<(), TrySendError<T>>{
-        Ok(self.senders[self.count.load(Ordering::SeqCst) % N].try_send(msg)?)
+        self.active().try_send(msg)
+    }
+
+    /// Send into the activate channel, handing the message back if it's still full after `timeout`.
+    pub fn send_timeout(&'_ self, msg: T, timeout: Duration) -> Result<(), SendTimeoutError<T>>{
+        self.active().send_timeout(msg, timeout)
+    }
+
+    /// Send into the activate channel, handing the message back if it's still full at `deadline`.
+    pub fn send_deadline(&'_ self, msg: T, deadline: Instant) -> Result<(), SendTimeoutError<T>>{
+        self.active().send_deadline(msg, deadline)
+    }
+
+    /// Close all the channels.
+    pub fn close(&self) -> bool{
+        close_lanes(&self.lanes[..])
+    }
+
+    pub fn is_closed(&self) -> bool{
+        self.lanes[0].is_closed()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.active().is_empty()
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.active().is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.active().len()
+    }
+
+    /// Returns the capacity of the active lane, `None` if it's unbounded.
+    pub fn capacity(&self) -> Option<usize>{
+        self.active().capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.handles.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.handles.receiver_count()
+    }
+
+    /// Returns the epoch the channel is in, which moves on every time the channel switches to a different lane.
+    pub fn epoch(&self) -> usize{
+        self.count.epoch()
+    }
+
+    /// Get a guard for `lane` without switching the channel.
+    pub fn guard_for(&self, lane: usize) -> Result<SwitchSyncSenderGuard<'_, T>, OutOfRangeError>{
+        if lane < N{
+            Ok(self.guard(SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: N })
+        }
+    }
+
+    /// The same as `guard_for`, so a specific lane can be used as `sender.send_to(lane)?.send(msg)`.
+    pub fn send_to(&self, lane: usize) -> Result<SwitchSyncSenderGuard<'_, T>, OutOfRangeError>{
+        self.guard_for(lane)
+    }
+
+    fn guard(&self, state: SwitchState) -> SwitchSyncSenderGuard<'_, T>{
+        SwitchSyncSenderGuard{
+            lane: &self.lanes[state.lane],
+            handles: &self.handles,
+            position: Position{ count: &self.count, state, lanes: Lanes::Fixed(N) },
+        }
+    }
+
+    /// Block until another handle switches the channel to a different lane, returning a guard for the new lane.
+    pub fn wait_for_switch(&self) -> SwitchSyncSenderGuard<'_, T>{
+        wait_for_epoch(&self.count, self.count.epoch(), None);
+        self.guard(self.count.state(N))
+    }
+
+    /// Like `wait_for_switch`, but gives up after `timeout`.
+    pub fn wait_for_switch_timeout(&self, timeout: Duration) -> Option<SwitchSyncSenderGuard<'_, T>>{
+        match Instant::now().checked_add(timeout){
+            Some(deadline) => self.wait_for_switch_deadline(deadline),
+            // Too far in the future to ever time out.
+            None => Some(self.wait_for_switch()),
+        }
+    }
+
+    /// Like `wait_for_switch`, but gives up at `deadline`.
+    pub fn wait_for_switch_deadline(&self, deadline: Instant) -> Option<SwitchSyncSenderGuard<'_, T>>{
+        if wait_for_epoch(&self.count, self.count.epoch(), Some(deadline)){
+            Some(self.guard(self.count.state(N)))
+        }else{
+            None
+        }
+    }
+
+    /// Get a guard for the active lane that keeps its own handle on the channel, so it can be moved into another thread.
+    pub fn get_owned_guard(&self) -> OwnedSwitchSyncSenderGuard<T>{
+        self.owned_guard(self.count.state(N))
+    }
+
+    /// Like `guard_for`, but the guard keeps its own handle on the channel, so it can be moved into another thread.
+    pub fn owned_guard_for(&self, lane: usize) -> Result<OwnedSwitchSyncSenderGuard<T>, OutOfRangeError>{
+        if lane < N{
+            Ok(self.owned_guard(SwitchState{ lane, epoch: self.count.epoch() }))
+        }else{
+            Err(OutOfRangeError{ lane, lanes: N })
+        }
+    }
+
+    fn owned_guard(&self, state: SwitchState) -> OwnedSwitchSyncSenderGuard<T>{
+        self.handles.add_sender();
+        OwnedSwitchSyncSenderGuard{
+            lanes: OwnedLanes::Fixed(self.lanes.clone()),
+            state,
+            handles: self.handles.clone(),
+            count: self.count.clone(),
+        }
+    }
+}
+
+impl<T, const N: usize> SwitchSyncSender<T, N, PERMITTED>{
+    /// Like `switch_outcome`, but with owned guards.
+    pub fn switch_outcome_owned(&self, op: SwitchOp) -> SwitchOutcome<OwnedSwitchSyncSenderGuard<T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state| self.owned_guard(state))
+    }
+
+    /// Like `switch_update`, but with owned guards.
+    pub fn switch_update_owned<F: FnMut(usize) -> Option<usize>>(&self, f: F) -> Result<OwnedSwitchSyncSenderGuard<T>, OwnedSwitchSyncSenderGuard<T>>{
+        let guard = |state: SwitchState| self.owned_guard(state);
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
+
+impl<T, const N: usize, const P: bool> Clone for SwitchSyncSender<T, N, P>{
+    fn clone(&self) -> Self{
+        self.handles.add_sender();
+        Self{
+            count: self.count.clone(),
+            lanes: self.lanes.clone(),
+            handles: self.handles.clone(),
+        }
+    }
+}
+
+impl<T, const N: usize, const P: bool> Drop for SwitchSyncSender<T, N, P>{
+    fn drop(&mut self){
+        if self.handles.remove_sender(){
+            self.close();
+        }
     }
 }
 
-#[derive(Clone)]
 pub struct SwitchSyncSenderGuard<'a, T>{
-    sender: &'a SyncSender<T>
+    pub(crate) lane: &'a Lane<T>,
+    pub(crate) handles: &'a Handles,
+    pub(crate) position: Position<'a>,
+}
+
+impl<'a, T> Clone for SwitchSyncSenderGuard<'a, T>{
+    fn clone(&self) -> Self{
+        Self{
+            lane: self.lane,
+            handles: self.handles,
+            position: self.position,
+        }
+    }
 }
 
 impl<'a, T> SwitchSyncSenderGuard<'a, T>{
     pub fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
-        Ok(self.sender.send(msg)?)
+        self.lane.send(msg)
     }
 
     pub fn try_send(&'_ self, msg: T) -> Result<(), TrySendError<T>>{
-        Ok(self.sender.try_send(msg)?)
+        self.lane.try_send(msg)
+    }
+
+    /// Send into the channel, handing the message back if it's still full after `timeout`.
+    pub fn send_timeout(&'_ self, msg: T, timeout: Duration) -> Result<(), SendTimeoutError<T>>{
+        self.lane.send_timeout(msg, timeout)
+    }
+
+    /// Send into the channel, handing the message back if it's still full at `deadline`.
+    pub fn send_deadline(&'_ self, msg: T, deadline: Instant) -> Result<(), SendTimeoutError<T>>{
+        self.lane.send_deadline(msg, deadline)
+    }
+
+    pub fn is_closed(&self) -> bool{
+        self.lane.is_closed()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.lane.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.lane.is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.lane.len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.lane.capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.handles.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.handles.receiver_count()
+    }
+
+    /// The epoch the channel was in when the guard was taken.
+    pub fn epoch(&self) -> usize{
+        self.position.state.epoch
+    }
+
+    /// Check if the channel hasn't switched lanes since the guard was taken.
+    pub fn is_current(&self) -> bool{
+        self.position.is_current()
     }
 }
 
-impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchSyncSender<T, N, PERMITTED>{
-    type Output = SwitchSyncSenderGuard<'a, T>;
+/// A `SwitchSyncSenderGuard` that isn't tied to the lifetime of its `SwitchSyncSender`.
+///
+/// It counts as a sender of the channel until it's dropped.
+pub struct OwnedSwitchSyncSenderGuard<T>{
+    pub(crate) lanes: OwnedLanes<T>,
+    pub(crate) state: SwitchState,
+    pub(crate) handles: Arc<Handles>,
+    pub(crate) count: Arc<SwitchCounter>,
+}
 
-    fn switch_add(&self, val: usize) -> SwitchSyncSenderGuard<'_, T>{
+impl<T> OwnedSwitchSyncSenderGuard<T>{
+    /// Borrow the guard as a `SwitchSyncSenderGuard`.
+    pub fn guard(&self) -> SwitchSyncSenderGuard<'_, T>{
         SwitchSyncSenderGuard{
-            sender: &self.senders[self.count.fetch_add(val, Ordering::SeqCst)]
+            lane: self.lanes.get(self.state.lane),
+            handles: &self.handles,
+            position: Position{ count: &self.count, state: self.state, lanes: self.lanes.lanes() },
         }
     }
 
-    fn switch_and(&self, val: usize) -> SwitchSyncSenderGuard<'_, T>{
-        SwitchSyncSenderGuard{
-            sender: &self.senders[self.count.fetch_and(val, Ordering::SeqCst)]
-        }
+    /// The lane this guard sends into.
+    pub fn lane(&self) -> usize{
+        self.state.lane
     }
 
-    fn switch_max(&self, val: usize) -> SwitchSyncSenderGuard<'_, T>{
-        SwitchSyncSenderGuard{
-            sender: &self.senders[self.count.fetch_max(val, Ordering::SeqCst)]
-        }
+    /// The epoch the channel was in when the guard was taken.
+    pub fn epoch(&self) -> usize{
+        self.state.epoch
     }
 
-    fn switch_min(&self, val: usize) -> SwitchSyncSenderGuard<'_, T>{
-        SwitchSyncSenderGuard{
-            sender: &self.senders[self.count.fetch_min(val, Ordering::SeqCst)]
-        }
+    /// Check if the channel hasn't switched lanes since the guard was taken.
+    pub fn is_current(&self) -> bool{
+        self.guard().is_current()
     }
 
-    fn switch_nand(&self, val: usize) -> SwitchSyncSenderGuard<'_, T>{
-        SwitchSyncSenderGuard{
-            sender: &self.senders[self.count.fetch_nand(val, Ordering::SeqCst)]
-        }
+    pub fn send(&'_ self, msg: T) -> Result<(), SendError<T>>{
+        self.guard().send(msg)
     }
 
-    fn switch_or(&self, val: usize) -> SwitchSyncSenderGuard<'_, T>{
-        SwitchSyncSenderGuard{
-            sender: &self.senders[self.count.fetch_or(val, Ordering::SeqCst)]
-        }
+    pub fn try_send(&'_ self, msg: T) -> Result<(), TrySendError<T>>{
+        self.guard().try_send(msg)
     }
 
-    fn switch_sub(&self, val: usize) -> SwitchSyncSenderGuard<'_, T>{
-        SwitchSyncSenderGuard{
-            sender: &self.senders[self.count.fetch_sub(val, Ordering::SeqCst)]
+    /// Send into the channel, handing the message back if it's still full after `timeout`.
+    pub fn send_timeout(&'_ self, msg: T, timeout: Duration) -> Result<(), SendTimeoutError<T>>{
+        self.guard().send_timeout(msg, timeout)
+    }
+
+    /// Send into the channel, handing the message back if it's still full at `deadline`.
+    pub fn send_deadline(&'_ self, msg: T, deadline: Instant) -> Result<(), SendTimeoutError<T>>{
+        self.guard().send_deadline(msg, deadline)
+    }
+
+    pub fn is_closed(&self) -> bool{
+        self.guard().is_closed()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.guard().is_empty()
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.guard().is_full()
+    }
+
+    pub fn len(&self) -> usize{
+        self.guard().len()
+    }
+
+    pub fn capacity(&self) -> Option<usize>{
+        self.guard().capacity()
+    }
+
+    /// Returns the number of senders for the channel.
+    pub fn sender_count(&self) -> usize{
+        self.handles.sender_count()
+    }
+
+    /// Returns the number of receivers for the channel.
+    pub fn receiver_count(&self) -> usize{
+        self.handles.receiver_count()
+    }
+}
+
+impl<T> Clone for OwnedSwitchSyncSenderGuard<T>{
+    fn clone(&self) -> Self{
+        self.handles.add_sender();
+        Self{
+            lanes: self.lanes.clone(),
+            state: self.state,
+            handles: self.handles.clone(),
+            count: self.count.clone(),
         }
     }
+}
 
-    fn switch_xor(&self, val: usize) -> SwitchSyncSenderGuard<'_, T>{
-        SwitchSyncSenderGuard{
-            sender: &self.senders[self.count.fetch_xor(val, Ordering::SeqCst)]
+impl<T> Drop for OwnedSwitchSyncSenderGuard<T>{
+    fn drop(&mut self){
+        if self.handles.remove_sender(){
+            self.lanes.close();
         }
     }
-}
\ No newline at end of file
+}
+
+impl<'a, T: 'static, const N: usize> Switcher<'a, T> for SwitchSyncSender<T, N, PERMITTED>{
+    type Output = SwitchSyncSenderGuard<'a, T>;
+
+    fn switch_outcome(&'a self, op: SwitchOp) -> SwitchOutcome<SwitchSyncSenderGuard<'a, T>>{
+        SwitchOutcome::from_states(switch_lane(&self.count, N, op), |state| self.guard(state))
+    }
+
+    fn switch_update<F: FnMut(usize) -> Option<usize>>(&'a self, f: F) -> Result<SwitchSyncSenderGuard<'a, T>, SwitchSyncSenderGuard<'a, T>>{
+        let guard = |state: SwitchState| self.guard(state);
+        update_lane(&self.count, N, f).map(guard).map_err(guard)
+    }
+}
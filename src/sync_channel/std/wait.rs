@@ -0,0 +1,54 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Wake, Waker};
+use std::thread::Thread;
+use std::time::Instant;
+use event_listener::EventListener;
+use crate::SwitchCounter;
+
+struct Unparker(Thread);
+
+impl Wake for Unparker{
+    fn wake(self: Arc<Self>){
+        self.0.unpark();
+    }
+}
+
+/// Block until any of `listeners` is notified, returning `false` if `deadline` passes first.
+pub(crate) fn wait_any(listeners: &mut [EventListener], deadline: Option<Instant>) -> bool{
+    let waker = Waker::from(Arc::new(Unparker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop{
+        if listeners.iter_mut().any(|listener| Pin::new(listener).poll(&mut cx).is_ready()){
+            return true;
+        }
+        match deadline{
+            None => std::thread::park(),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline{
+                    return false;
+                }
+                std::thread::park_timeout(deadline - now);
+            },
+        }
+    }
+}
+
+/// Block until `count` has moved on from `epoch`, returning `false` if `deadline` passes first.
+pub(crate) fn wait_for_epoch(count: &SwitchCounter, epoch: usize, deadline: Option<Instant>) -> bool{
+    loop{
+        // Start listening before checking, so a switch in between isn't missed.
+        let listener = count.listen();
+        if count.epoch() != epoch{
+            return true;
+        }
+        match deadline{
+            None => listener.wait(),
+            Some(deadline) => if !listener.wait_deadline(deadline){
+                return count.epoch() != epoch;
+            },
+        }
+    }
+}